@@ -0,0 +1,110 @@
+use std::convert::TryFrom;
+
+use solana_program::program_error::ProgramError;
+
+// Raw amount as a decimal string using the mint's decimals, with trailing fractional zeros trimmed
+pub fn ui_amount(raw: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", raw, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+// Inverse of ui_amount. Digits beyond the mint's precision are rounded half up;
+// anything that isn't a plain non-negative decimal, or doesn't fit in a u64, is rejected
+pub fn amount_from_ui(ui: &str, decimals: u8) -> Result<u64, ProgramError> {
+    let decimals = decimals as usize;
+    let (whole, fraction) = match ui.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (ui, ""),
+    };
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (kept, dropped) = fraction.split_at(fraction.len().min(decimals));
+    let mut amount: u64 = 0;
+    for digit in whole.bytes().chain(kept.bytes()) {
+        amount = amount
+            .checked_mul(10)
+            .and_then(|amount| amount.checked_add((digit - b'0') as u64))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+    }
+    // Short fractions are scaled up to the full precision, a zero amount stays zero however large the scale
+    if amount != 0 {
+        let scale = u32::try_from(decimals - kept.len())
+            .ok()
+            .and_then(|exponent| 10u64.checked_pow(exponent))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        amount = amount.checked_mul(scale).ok_or(ProgramError::ArithmeticOverflow)?;
+    }
+
+    if dropped.as_bytes().first().is_some_and(|digit| *digit >= b'5') {
+        amount = amount.checked_add(1).ok_or(ProgramError::ArithmeticOverflow)?;
+    }
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ui_amount_places_the_decimal_point_and_trims_zeros() {
+        assert_eq!(ui_amount(0, 6), "0");
+        assert_eq!(ui_amount(1, 6), "0.000001");
+        assert_eq!(ui_amount(1_500_000, 6), "1.5");
+        assert_eq!(ui_amount(2_000_000, 6), "2");
+        assert_eq!(ui_amount(42, 0), "42");
+        assert_eq!(ui_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(ui_amount(u64::MAX, 25), "0.0000018446744073709551615");
+    }
+
+    #[test]
+    fn amount_from_ui_scales_to_the_mint_precision() {
+        assert_eq!(amount_from_ui("1.5", 6), Ok(1_500_000));
+        assert_eq!(amount_from_ui("1", 6), Ok(1_000_000));
+        assert_eq!(amount_from_ui(".25", 2), Ok(25));
+        assert_eq!(amount_from_ui("3.", 2), Ok(300));
+        assert_eq!(amount_from_ui("0", 30), Ok(0));
+        assert_eq!(amount_from_ui("18446744073.709551615", 9), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn amount_from_ui_rounds_extra_digits_half_up() {
+        assert_eq!(amount_from_ui("0.0000014", 6), Ok(1));
+        assert_eq!(amount_from_ui("0.0000015", 6), Ok(2));
+        assert_eq!(amount_from_ui("0.0000009", 6), Ok(1));
+        assert_eq!(amount_from_ui("1.49", 0), Ok(1));
+        assert_eq!(amount_from_ui("1.5", 0), Ok(2));
+    }
+
+    #[test]
+    fn amount_from_ui_round_trips_ui_amount() {
+        for (raw, decimals) in [(0, 6), (1, 6), (1_500_000, 6), (123_456_789, 3), (u64::MAX, 9), (u64::MAX, 0)] {
+            assert_eq!(amount_from_ui(&ui_amount(raw, decimals), decimals), Ok(raw));
+        }
+    }
+
+    #[test]
+    fn amount_from_ui_rejects_what_does_not_fit() {
+        assert_eq!(amount_from_ui("18446744073709551616", 0), Err(ProgramError::ArithmeticOverflow));
+        assert_eq!(amount_from_ui("18446744073709551615.5", 0), Err(ProgramError::ArithmeticOverflow));
+        assert_eq!(amount_from_ui("18446744073.709551616", 9), Err(ProgramError::ArithmeticOverflow));
+        assert_eq!(amount_from_ui("1", 20), Err(ProgramError::ArithmeticOverflow));
+    }
+
+    #[test]
+    fn amount_from_ui_rejects_malformed_input() {
+        for ui in ["", ".", "-1", "+1", "1.2.3", "1,5", " 1", "1e6", "abc"] {
+            assert_eq!(amount_from_ui(ui, 6), Err(ProgramError::InvalidArgument), "{:?}", ui);
+        }
+    }
+}
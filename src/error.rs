@@ -0,0 +1,159 @@
+use std::fmt;
+
+use solana_program::{msg, program_error::ProgramError};
+
+// Codes are part of the program's interface: never renumber a variant, only append new ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CustomError {
+    MaxSupplyExceeded = 0x1,
+    UnauthorizedMint = 0x2,
+    InvalidAmount = 0x3,
+    ArithmeticOverflow = 0x4,
+    MintOperationsExceeded = 0x5,
+    Unauthorized = 0x6,
+    NoFreezeAuthority = 0x7,
+    InvalidProof = 0x8,
+    AlreadyClaimed = 0x9,
+    FreezeAuthorityMismatch = 0xA,
+    TransferLimitExceeded = 0xB,
+    TimelockNotElapsed = 0xC,
+    MintMismatch = 0xD,
+    TransfersLocked = 0xE,
+    TooManyAccounts = 0xF,
+    DailyMintLimitExceeded = 0x10,
+    RecipientNotOptedIn = 0x11,
+    AccountNotEmpty = 0x12,
+    AddressNotWhitelisted = 0x13,
+    MintingFinalized = 0x14,
+    VotingClosed = 0x15,
+    ProposalNotPassed = 0x16,
+    InstructionExpired = 0x17,
+    AccountFrozen = 0x18,
+    EmptyInstructionData = 0x19,
+    InsufficientLiquidity = 0x1A,
+    DecimalsMismatch = 0x1B,
+    PerAccountCapExceeded = 0x1C,
+    InvalidMint = 0x1D,
+    MigrationInProgress = 0x1E,
+    OwnerAccountLimitExceeded = 0x1F,
+}
+
+impl CustomError {
+    // Maps a ProgramError::Custom code back to its variant, for clients and logs
+    pub fn from_code(code: u32) -> Option<Self> {
+        let error = match code {
+            0x1 => CustomError::MaxSupplyExceeded,
+            0x2 => CustomError::UnauthorizedMint,
+            0x3 => CustomError::InvalidAmount,
+            0x4 => CustomError::ArithmeticOverflow,
+            0x5 => CustomError::MintOperationsExceeded,
+            0x6 => CustomError::Unauthorized,
+            0x7 => CustomError::NoFreezeAuthority,
+            0x8 => CustomError::InvalidProof,
+            0x9 => CustomError::AlreadyClaimed,
+            0xA => CustomError::FreezeAuthorityMismatch,
+            0xB => CustomError::TransferLimitExceeded,
+            0xC => CustomError::TimelockNotElapsed,
+            0xD => CustomError::MintMismatch,
+            0xE => CustomError::TransfersLocked,
+            0xF => CustomError::TooManyAccounts,
+            0x10 => CustomError::DailyMintLimitExceeded,
+            0x11 => CustomError::RecipientNotOptedIn,
+            0x12 => CustomError::AccountNotEmpty,
+            0x13 => CustomError::AddressNotWhitelisted,
+            0x14 => CustomError::MintingFinalized,
+            0x15 => CustomError::VotingClosed,
+            0x16 => CustomError::ProposalNotPassed,
+            0x17 => CustomError::InstructionExpired,
+            0x18 => CustomError::AccountFrozen,
+            0x19 => CustomError::EmptyInstructionData,
+            0x1A => CustomError::InsufficientLiquidity,
+            0x1B => CustomError::DecimalsMismatch,
+            0x1C => CustomError::PerAccountCapExceeded,
+            0x1D => CustomError::InvalidMint,
+            0x1E => CustomError::MigrationInProgress,
+            0x1F => CustomError::OwnerAccountLimitExceeded,
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    // Logs a readable description of a program error, falling back to the built-in text for non-custom errors
+    pub fn print(error: &ProgramError) {
+        match error {
+            ProgramError::Custom(code) => match CustomError::from_code(*code) {
+                Some(custom) => msg!("Error 0x{:x}: {}", code, custom),
+                None => msg!("Unknown custom error 0x{:x}", code),
+            },
+            other => msg!("{}", other),
+        }
+    }
+}
+
+impl fmt::Display for CustomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            CustomError::MaxSupplyExceeded => "Minting would exceed the max supply",
+            CustomError::UnauthorizedMint => "Signer is not allowed to mint",
+            CustomError::InvalidAmount => "Amount is zero, malformed or out of range",
+            CustomError::ArithmeticOverflow => "Arithmetic overflow",
+            CustomError::MintOperationsExceeded => "Max number of mint operations reached",
+            CustomError::Unauthorized => "Signer is not authorized for this action",
+            CustomError::NoFreezeAuthority => "Mint has no freeze authority",
+            CustomError::InvalidProof => "Merkle proof does not match the airdrop root",
+            CustomError::AlreadyClaimed => "Airdrop leaf has already been claimed",
+            CustomError::FreezeAuthorityMismatch => "Mint freeze authority differs from the expected one",
+            CustomError::TransferLimitExceeded => "Transfer exceeds the max transfer amount",
+            CustomError::TimelockNotElapsed => "Timelock has not elapsed yet",
+            CustomError::MintMismatch => "Accounts belong to different mints",
+            CustomError::TransfersLocked => "Transfers are still locked",
+            CustomError::TooManyAccounts => "More accounts passed than the instruction reads",
+            CustomError::DailyMintLimitExceeded => "Minting would exceed the daily mint limit",
+            CustomError::RecipientNotOptedIn => "Recipient has not opted in to receive transfers",
+            CustomError::AccountNotEmpty => "Token account still holds a balance",
+            CustomError::AddressNotWhitelisted => "Wallet is not on the transfer whitelist",
+            CustomError::MintingFinalized => "Mint authority has been removed, no more tokens can be minted",
+            CustomError::VotingClosed => "Voting on this proposal has closed",
+            CustomError::ProposalNotPassed => "Proposal did not reach quorum or a majority",
+            CustomError::InstructionExpired => "Instruction was submitted after its valid-until slot",
+            CustomError::AccountFrozen => "Token account is frozen",
+            CustomError::EmptyInstructionData => "Instruction data is empty",
+            CustomError::InsufficientLiquidity => "Pool vaults hold less than the minimum liquidity",
+            CustomError::DecimalsMismatch => "Mint decimals do not match the config",
+            CustomError::PerAccountCapExceeded => "Mint would exceed the destination's per-account cap",
+            CustomError::InvalidMint => "Account is not an initialized token mint",
+            CustomError::MigrationInProgress => "Config migration is in progress",
+            CustomError::OwnerAccountLimitExceeded => "Owner already has the most token accounts the config allows",
+        };
+        f.write_str(description)
+    }
+}
+
+impl std::error::Error for CustomError {}
+
+impl From<CustomError> for ProgramError {
+    fn from(e: CustomError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_maps_back_to_its_variant() {
+        for code in 0x1..=0x1F {
+            let error = CustomError::from_code(code).expect("assigned code");
+            assert_eq!(error as u32, code);
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+    }
+
+    #[test]
+    fn unassigned_codes_have_no_variant() {
+        assert_eq!(CustomError::from_code(0), None);
+        assert_eq!(CustomError::from_code(0x20), None);
+        assert_eq!(CustomError::from_code(u32::MAX), None);
+    }
+}
@@ -1,180 +1,4386 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
+    instruction::Instruction,
+    keccak,
+    log::sol_log_data,
     msg,
-    program::{invoke},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
 };
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use spl_token::{
-    instruction::{mint_to, transfer, burn},
-    state::Mint,
+    instruction::{
+        mint_to, transfer, transfer_checked, burn, approve, revoke, freeze_account, thaw_account,
+        initialize_account3, sync_native, close_account, set_authority, AuthorityType,
+    },
+    state::{Account, AccountState, Mint},
 };
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account,
+};
+use solana_program::program_option::COption;
 use solana_program::program_pack::Pack;
 
+pub mod error;
+use error::CustomError;
+
+pub mod math;
+
+// Display helpers for off-chain callers, never part of the on-chain program
+#[cfg(not(target_os = "solana"))]
+pub mod client;
+
+// Success logs on the transfer, mint and burn paths. The quiet-logs feature compiles them out to save
+// compute in high-throughput deployments; error logs are always kept. The saving can't be seen under
+// solana-program-test, which runs the processor natively with a sol_log that charges nothing. On-chain each log
+// is a sol_log syscall costing max(100, message length) units, plus formatting the message first, mostly the
+// base58 of the pubkeys in it, so it has to be measured on an SBF build run in solana-test-validator
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        #[cfg(not(feature = "quiet-logs"))]
+        msg!($($arg)*);
+    };
+}
+
+// Bumped only when an existing field changes shape. Appending a field keeps the version: an account written
+// before it ends early, MigrateConfig pads it out with zeros, and a zeroed field always means disabled
+pub const CONFIG_VERSION: u8 = 4;
+// A max_supply of u64::MAX means the token is uncapped; checked_add still guards the supply itself
+pub const UNLIMITED_SUPPLY: u64 = u64::MAX;
+pub const MINT_WINDOW_SECONDS: i64 = 86_400;
+// Year length used to pro-rate post-cap inflation
+pub const SECONDS_PER_YEAR: i64 = 31_536_000;
+pub const MAX_BATCH_REVOKE: usize = 10;
+pub const MAX_ADMIN_SIGNERS: usize = 3;
+pub const MAX_FEE_EXEMPT: usize = 4;
+// Each vault must already hold at least this much when its pool is initialized, so quotes mean something from the start
+pub const MIN_POOL_LIQUIDITY: u64 = 1_000;
+// Set on the transfer (0) or mint (1) tag to send the amount as LEB128 instead of 8 LE bytes
+pub const VARINT_AMOUNT_FLAG: u8 = 0x80;
+// Borsh size of the current TokenConfig layout, field by field; any field added to TokenConfig must be added here too
+pub const CONFIG_LEN: usize = 1 + 8 + 1 + 32 // version, max_supply, initialized, admin_pubkey
+    + 8 + 8 + 2 + 32 // max_mint_operations, mint_count, mint_rewards_bps, rewards_vault
+    + 32 + 32 + 1 // merkle_root, expected_freeze_authority, bump
+    + 8 + 8 // min_mint_amount, max_transfer_amount
+    + 32 * MAX_ADMIN_SIGNERS + 1 + 8 // admin_signers, threshold, timelock_delay
+    + (1 + 1 + 1 + 32 + 8) // pending_action
+    + 1 + 8 + 2 + 32 // decimals, transfers_unlocked_ts, transfer_fee_bps, fee_treasury
+    + 8 + 8 + 32 // current_epoch, epoch_started_ts, mint
+    + 8 + 8 + 8 // daily_mint_limit, minted_in_window, window_start_ts
+    + 1 + 1 + 32 + 32 // require_opt_in, whitelist_mode, mint_admin, config_admin
+    + 8 + 8 + 32 * MAX_FEE_EXEMPT // proposal_count, quorum_votes, fee_exempt
+    + 8 + 32 // sol_fee_lamports, sol_fee_treasury
+    + 2 + 8 + 8 // post_cap_annual_inflation_bps, cap_reached_ts, post_cap_minted
+    + 8 + 32 // high_value_threshold, cosigner_pubkey
+    + 8 // per_account_mint_cap
+    + 2 + 32 // burn_treasury_bps, burn_treasury
+    + 1 + 1 // migration_in_progress, require_checked_transfers
+    + 32 // airdrop_vault
+    + 16 + 16 // total_minted, total_burned
+    + 8 // max_accounts_per_owner
+    + 8 + 32 // compound_threshold, yield_vault
+    + 8 + 8 + 8 + 8 // burn_breaker_threshold, burn_breaker_window, burned_in_window, burn_window_start_ts
+    + 1; // mint_permanently_disabled
+// Where total_minted starts, before the fields appended since version 3 and the counters themselves; version 3
+// stored total_minted and total_burned there as u64s
+const V3_COUNTERS_OFFSET: usize = CONFIG_LEN - (8 + 8 + 32 + 32 + 1) - 32;
+// Size of a config written by the first release, a bare TokenConfigV1: max_supply, initialized, admin_pubkey
+pub const LEGACY_CONFIG_LEN: usize = 8 + 1 + 32;
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault_auth";
+pub const CLAIMS_SEED: &[u8] = b"claims";
+pub const VESTING_SEED: &[u8] = b"vesting";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const POOL_SEED: &[u8] = b"pool";
+pub const ESCROW_SEED: &[u8] = b"escrow";
+pub const OPT_IN_SEED: &[u8] = b"opt_in";
+pub const WHITELIST_SEED: &[u8] = b"whitelist";
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+pub const VOTE_SEED: &[u8] = b"vote";
+pub const VOTE_ACCOUNT_SEED: &[u8] = b"vote_account";
+pub const MIGRATION_SEED: &[u8] = b"migration";
+pub const MINT_RECEIPT_SEED: &[u8] = b"mint_receipt";
+pub const SUPPLY_HISTORY_SEED: &[u8] = b"supply_history";
+pub const OWNER_ACCOUNTS_SEED: &[u8] = b"owner_accounts";
+// Changes to max_supply kept on-chain; older ones are overwritten, the events keep the full record
+pub const SUPPLY_HISTORY_LEN: usize = 8;
+// Proposal parameter tags; only the supply cap can be voted on for now
+pub const PROPOSAL_PARAM_MAX_SUPPLY: u8 = 0;
+pub const MEMO_PROGRAM_ID: Pubkey = solana_program::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+// Keeps the memo CPI's compute cost bounded
+pub const MAX_MEMO_LEN: usize = 256;
+// One bit per airdrop leaf index
+pub const CLAIM_BITMAP_LEN: usize = 1024;
+
 #[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
 pub struct TokenConfig {
+    pub version: u8,
     pub max_supply: u64,
     pub initialized: bool,
     pub admin_pubkey: Pubkey,
+    // Cap on the number of mint instructions over the token's lifetime, 0 means no cap
+    pub max_mint_operations: u64,
+    pub mint_count: u64,
+    // Share of every mint, in basis points, that is forwarded to the rewards vault
+    pub mint_rewards_bps: u16,
+    pub rewards_vault: Pubkey,
+    // Root of the airdrop merkle tree, all zeros while no airdrop is open
+    pub merkle_root: [u8; 32],
+    // Freeze authority the mint is expected to keep, unchecked while left as the default pubkey
+    pub expected_freeze_authority: Pubkey,
+    // Canonical bump of the config PDA
+    pub bump: u8,
+    // Smallest amount a single mint may issue, 0 disables the check
+    pub min_mint_amount: u64,
+    // Largest amount a single transfer may move, 0 means no limit
+    pub max_transfer_amount: u64,
+    // M-of-N cosigners for minting, a threshold of 0 or 1 leaves the single admin in charge
+    pub admin_signers: [Pubkey; MAX_ADMIN_SIGNERS],
+    pub threshold: u8,
+    // Seconds a queued config change must wait before it can be executed, 0 applies changes immediately
+    pub timelock_delay: i64,
+    pub pending_action: PendingAction,
+    // Decimals of the mint recorded at initialize, for cross-checking later amounts
+    pub decimals: u8,
+    // Transfers are rejected before this unix timestamp, mint and burn are unaffected; 0 leaves transfers open
+    pub transfers_unlocked_ts: i64,
+    // Tax on every transfer, in basis points, sent to fee_treasury; 0 disables it
+    pub transfer_fee_bps: u16,
+    pub fee_treasury: Pubkey,
+    // Reward epoch bumped by the admin, with the time it started, for off-chain snapshots to anchor on
+    pub current_epoch: u64,
+    pub epoch_started_ts: i64,
+    // Mint this config governs; the config account is the PDA of [CONFIG_SEED, mint]
+    pub mint: Pubkey,
+    // Rolling 24h cap on minted tokens, 0 disables it; the window restarts on the first mint after it lapses
+    pub daily_mint_limit: u64,
+    pub minted_in_window: u64,
+    pub window_start_ts: i64,
+    // When set, transfers only go to wallets with an opt-in record that is opted in or exempted by the admin
+    pub require_opt_in: bool,
+    // When set, both the sender and the recipient wallet need a whitelist entry for a transfer to go through
+    pub whitelist_mode: bool,
+    // Role keys split off from admin_pubkey; left as the default pubkey they fall back to the admin,
+    // which is how configs written before the split keep working. Once set, admin_pubkey itself only
+    // assigns roles and admin signers, every other privileged handler checks the role it belongs to
+    pub mint_admin: Pubkey,
+    pub config_admin: Pubkey,
+    // Token-weighted governance: proposals are numbered from proposal_count, and pass with at least
+    // quorum_votes cast and more yes than no votes. A quorum of 0 disables governance
+    pub proposal_count: u64,
+    pub quorum_votes: u64,
+    // Token accounts (treasury, pool vaults) that neither pay nor trigger the transfer fee; unused slots are default
+    pub fee_exempt: [Pubkey; MAX_FEE_EXEMPT],
+    // Flat SOL charge per transfer, paid by a signing payer to sol_fee_treasury; 0 disables it
+    pub sol_fee_lamports: u64,
+    pub sol_fee_treasury: Pubkey,
+    // Hybrid supply: once max_supply is reached, minting continues at this annual rate of the cap, pro-rated
+    // from cap_reached_ts, with post_cap_minted tracking what has been issued since. 0 keeps the cap hard
+    pub post_cap_annual_inflation_bps: u16,
+    pub cap_reached_ts: i64,
+    pub post_cap_minted: u64,
+    // Mints above high_value_threshold also need cosigner_pubkey's signature; a threshold of 0 disables it
+    pub high_value_threshold: u64,
+    pub cosigner_pubkey: Pubkey,
+    // Most any one destination account may receive through mints over its lifetime, 0 means no cap
+    pub per_account_mint_cap: u64,
+    // Share of every burn, in basis points, sent to the burn_treasury token account instead of burned; 0 burns it all
+    pub burn_treasury_bps: u16,
+    pub burn_treasury: Pubkey,
+    // Raised by the config admin before a multi-step migration, and doubling as the program's pause: every handler
+    // that moves tokens or changes the supply is refused until MigrateConfig finishes or the admin clears it
+    pub migration_in_progress: bool,
+    // When set, plain transfers are refused and only TransferChecked, which states the decimals, goes through
+    pub require_checked_transfers: bool,
+    // Token account claims are paid from, held by the config's vault authority; claims against any other vault fail
+    pub airdrop_vault: Pubkey,
+    // Everything minted and burned through this program, starting from the supply the mint had when the config
+    // was initialized. AuditSupply compares their difference to the mint's actual supply. Unlike the supply they
+    // only ever grow, so they are u128 and a long-lived token can't run them past u64::MAX
+    pub total_minted: u128,
+    pub total_burned: u128,
+    // Most token accounts one owner may receive mints into, 0 means no limit. Accounts count from the mint that
+    // creates their receipt, so ones minted to before the limit was set are not counted
+    pub max_accounts_per_owner: u64,
+    // Once the fee_treasury holds at least compound_threshold, the transfer that takes it there sweeps the balance
+    // into yield_vault. The treasury must then be held by the config's vault authority; a threshold of 0 disables it
+    pub compound_threshold: u64,
+    pub yield_vault: Pubkey,
+    // Circuit breaker: burns through the config that add up to more than burn_breaker_threshold within
+    // burn_breaker_window seconds raise migration_in_progress, which holds everything until the config admin
+    // clears it. The window restarts on the first burn after it lapses; a threshold of 0 disables the breaker
+    pub burn_breaker_threshold: u64,
+    pub burn_breaker_window: i64,
+    pub burned_in_window: u64,
+    pub burn_window_start_ts: i64,
+    // Set by DisableMint, which also removes the mint authority; once set, every mint fails straight away
+    pub mint_permanently_disabled: bool,
+}
+
+impl TokenConfig {
+    pub fn mint_admin(&self) -> Pubkey {
+        if self.mint_admin == Pubkey::default() { self.admin_pubkey } else { self.mint_admin }
+    }
+
+    pub fn config_admin(&self) -> Pubkey {
+        if self.config_admin == Pubkey::default() { self.admin_pubkey } else { self.config_admin }
+    }
+}
+
+// A config change waiting out the timelock, using the same field tags as UpdateConfig
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct PendingAction {
+    pub queued: bool,
+    pub field: u8,
+    pub value_len: u8,
+    pub value: [u8; 32],
+    pub earliest_exec_ts: i64,
+}
+
+// Lives at the PDA of [POOL_SEED, mint_a, mint_b]
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct PoolConfig {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub lp_mint: Pubkey,
+    pub fee_bps: u16,
+    pub initialized: bool,
+}
+
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct VestingSchedule {
+    pub beneficiary: Pubkey,
+    pub vault: Pubkey,
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+    pub total: u64,
+    pub released: u64,
+}
+
+impl VestingSchedule {
+    // Linear from start_ts to end_ts, with nothing unlocked before the cliff
+    pub fn vested_amount(&self, now: i64) -> u64 {
+        if now < self.cliff_ts {
+            0
+        } else if now >= self.end_ts {
+            self.total
+        } else {
+            let elapsed = (now - self.start_ts) as u128;
+            let duration = (self.end_ts - self.start_ts) as u128;
+            (self.total as u128 * elapsed / duration) as u64
+        }
+    }
+}
+
+// Lives at the PDA of [MINT_RECEIPT_SEED, config, destination token account], counting what mints have sent it
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct MintReceipt {
+    pub received: u64,
+}
+
+// Lives at the PDA of [OWNER_ACCOUNTS_SEED, config, owner], counting the owner's token accounts mints have reached
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct OwnerAccounts {
+    pub count: u64,
+}
+
+// One change to max_supply; signer is the admin, or the executed proposal's account for a governance change
+#[derive(Default, Clone, Copy, BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct SupplyChange {
+    pub old_max_supply: u64,
+    pub new_max_supply: u64,
+    pub timestamp: i64,
+    pub signer: Pubkey,
+}
+
+// Lives at the PDA of [SUPPLY_HISTORY_SEED, config]: a ring of the last SUPPLY_HISTORY_LEN cap changes, with
+// next the slot the coming change overwrites and len how many slots are filled
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct SupplyHistory {
+    pub next: u8,
+    pub len: u8,
+    pub entries: [SupplyChange; SUPPLY_HISTORY_LEN],
+}
+
+impl SupplyHistory {
+    pub fn push(&mut self, change: SupplyChange) {
+        self.entries[self.next as usize] = change;
+        self.next = ((self.next as usize + 1) % SUPPLY_HISTORY_LEN) as u8;
+        self.len = (self.len as usize + 1).min(SUPPLY_HISTORY_LEN) as u8;
+    }
+
+    // Filled entries, oldest first
+    pub fn recent(&self) -> Vec<SupplyChange> {
+        let start = (self.next as usize + SUPPLY_HISTORY_LEN - self.len as usize) % SUPPLY_HISTORY_LEN;
+        (0..self.len as usize).map(|i| self.entries[(start + i) % SUPPLY_HISTORY_LEN]).collect()
+    }
+}
+
+// Lives at the PDA of [OPT_IN_SEED, config, wallet]
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct OptInRecord {
+    pub opted_in: bool,
+    pub exempt: bool,
+}
+
+// Lives at the PDA of [WHITELIST_SEED, config, wallet], managed by the admin
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct WhitelistEntry {
+    pub whitelisted: bool,
+}
+
+// Lives at the PDA of [PROPOSAL_SEED, config, id], voting closes at end_ts
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct Proposal {
+    pub id: u64,
+    pub param: u8,
+    pub new_value: u64,
+    pub yes_votes: u64,
+    pub no_votes: u64,
+    pub end_ts: i64,
+    pub executed: bool,
+}
+
+// Marks that a wallet voted on a proposal, at the PDA of [VOTE_SEED, proposal, voter]. The weight sits in the
+// proposal's vote vault until the voter reclaims it into token_account after voting closes
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct VoteRecord {
+    pub support: bool,
+    pub weight: u64,
+    pub token_account: Pubkey,
+    pub reclaimed: bool,
+}
+
+// Lives at the PDA of [MIGRATION_SEED, old_mint, new_mint], which is also the new mint's mint authority.
+// Every old token burned mints ratio_num / ratio_den new tokens
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct TokenMigration {
+    pub old_mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub ratio_num: u64,
+    pub ratio_den: u64,
+    pub initialized: bool,
+}
+
+// Audit trail for privileged changes, logged as sol_log_data(["admin_event", borsh(AdminEvent)]) so the
+// history can be rebuilt off-chain. Config values use the same encoding as the UpdateConfig value bytes
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub enum AdminEvent {
+    ConfigUpdated { signer: Pubkey, field: u8, old_value: Vec<u8>, new_value: Vec<u8> },
+    RolesChanged {
+        signer: Pubkey,
+        old_mint_admin: Pubkey,
+        new_mint_admin: Pubkey,
+        old_config_admin: Pubkey,
+        new_config_admin: Pubkey,
+    },
+    MintAuthorityRotated { signer: Pubkey, old_authority: Pubkey, new_authority: Pubkey },
+    AdminSignersChanged { signer: Pubkey, old_threshold: u8, new_threshold: u8 },
+    FeeExemptChanged { signer: Pubkey, old_exempt: [Pubkey; MAX_FEE_EXEMPT], new_exempt: [Pubkey; MAX_FEE_EXEMPT] },
+    // Governance changes have no admin behind them, so signer is the executed proposal's account
+    SupplyCapChanged { signer: Pubkey, old_max_supply: u64, new_max_supply: u64 },
+    WhitelistChanged { signer: Pubkey, wallet: Pubkey, whitelisted: bool },
+    OptInExemptChanged { signer: Pubkey, wallet: Pubkey, exempt: bool },
+    VestingCreated {
+        signer: Pubkey,
+        beneficiary: Pubkey,
+        vault: Pubkey,
+        start_ts: i64,
+        cliff_ts: i64,
+        end_ts: i64,
+        total: u64,
+    },
+    MigrationInitialized { signer: Pubkey, old_mint: Pubkey, new_mint: Pubkey, ratio_num: u64, ratio_den: u64 },
+    // The first release wrote no version byte, its configs are reported as version 1
+    ConfigMigrated { signer: Pubkey, old_version: u8, new_version: u8 },
+    ActionQueued { signer: Pubkey, field: u8, value: Vec<u8>, earliest_exec_ts: i64 },
+    // Emitted by the burn that trips the breaker; signer is that burn's authority
+    BurnBreakerTripped { signer: Pubkey, burned_in_window: u64, window_start_ts: i64 },
+    // Terminal: the supply it reports is the most this mint will ever have
+    SupplyFrozen { signer: Pubkey, final_supply: u64 },
+}
+
+impl AdminEvent {
+    pub fn emit(&self) -> ProgramResult {
+        sol_log_data(&[b"admin_event", &self.try_to_vec()?]);
+        Ok(())
+    }
+}
+
+// Tokens held in a vault owned by the vault authority PDA until unlock_ts, then paid out to the recipient
+#[derive(Default, BorshSerialize, BorshDeserialize, Debug)]
+pub struct Escrow {
+    pub depositor: Pubkey,
+    pub recipient: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub unlock_ts: i64,
+    pub released: bool,
+}
+
+// Layout written by the first release, before any of the newer fields existed
+#[derive(Default, BorshSerialize, BorshDeserialize)]
+struct TokenConfigV1 {
+    max_supply: u64,
+    initialized: bool,
+    admin_pubkey: Pubkey,
+}
+
+// Reads the config at whatever layout it was written with and upgrades it to CONFIG_VERSION in memory;
+// MigrateConfig is what writes the upgrade back
+pub fn migrate(data: &mut [u8]) -> Result<TokenConfig, ProgramError> {
+    // The first release wrote a bare TokenConfigV1 into an account of exactly its size, with no version byte
+    // in front, so its first byte is just the low byte of max_supply and only the length identifies it
+    if data.len() == LEGACY_CONFIG_LEN {
+        let v1 = TokenConfigV1::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+        return Ok(TokenConfig {
+            version: CONFIG_VERSION,
+            max_supply: v1.max_supply,
+            initialized: v1.initialized,
+            admin_pubkey: v1.admin_pubkey,
+            ..TokenConfig::default()
+        });
+    }
+
+    let version = *data.first().ok_or(ProgramError::InvalidAccountData)?;
+    match version {
+        // A freshly allocated account is all zeros, which reads as a default config
+        0 | CONFIG_VERSION => TokenConfig::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData),
+        // Version 3 stored both counters as u64, widened in place here. It ended at them, so the fields appended
+        // since stay zero
+        3 => {
+            let (common, counters) = data.split_at(V3_COUNTERS_OFFSET);
+            let mut widened = common.to_vec();
+            for counter in counters[..16].chunks_exact(8) {
+                let value = u64::from_le_bytes(counter.try_into().map_err(|_| ProgramError::InvalidAccountData)?);
+                widened.extend_from_slice(&(value as u128).to_le_bytes());
+            }
+            widened.resize(CONFIG_LEN, 0);
+            let mut token_config =
+                TokenConfig::deserialize(&mut &widened[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+            token_config.version = CONFIG_VERSION;
+            Ok(token_config)
+        },
+        // Version 2 covers every layout from the first versioned one up to airdrop_vault, since fields were only
+        // appended under it. Whatever an account lacks is padded in as zeros, so the counters count from the upgrade on
+        2 => {
+            let mut token_config =
+                TokenConfig::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+            token_config.version = CONFIG_VERSION;
+            Ok(token_config)
+        },
+        _ => {
+            msg!("Unknown config version {}", version);
+            Err(ProgramError::InvalidAccountData)
+        },
+    }
+}
+
+// Upgrades a config written by an older program version in place, growing the account to CONFIG_LEN when
+// the new layout doesn't fit. Accounts are config and admin, then the mint for a config that recorded none, then
+// payer and system program, which are only needed to grow the account and cover the extra rent
+fn process_migrate_config(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    if config_account.owner != program_id {
+        msg!("Config account {} is not owned by this program", config_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Migrate a copy, since the buffer may be too small to take the upgrade until it's reallocated.
+    // Fields appended since the account was sized read as zero, which leaves each of them disabled
+    let mut data = config_account.try_borrow_data()?.to_vec();
+    let old_version = if data.len() == LEGACY_CONFIG_LEN { 1 } else { data.first().copied().unwrap_or_default() };
+    if data.len() != LEGACY_CONFIG_LEN && data.len() < CONFIG_LEN {
+        data.resize(CONFIG_LEN, 0);
+    }
+    let mut token_config = migrate(&mut data)?;
+    if !token_config.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    require_admin(admin, &token_config.config_admin(), "the config admin can migrate the config")?;
+
+    // Configs from before the PDA recorded no mint, so they are bound to the one their admin is the authority
+    // of. They stay at their keypair address and keep a bump of 0, which is what exempts them from the PDA check
+    if token_config.mint == Pubkey::default() {
+        let mint_account = next_account_info(account_info_iter)?;
+        if *mint_account.owner != spl_token::id() {
+            msg!("Mint {} is not owned by the token program", mint_account.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mint_state = unpack_mint(mint_account)?;
+        if mint_state.mint_authority != COption::Some(token_config.admin_pubkey) {
+            msg!("Unauthorized: the config admin is not the mint authority of {}", mint_account.key);
+            return Err(CustomError::UnauthorizedMint.into());
+        }
+        token_config.mint = *mint_account.key;
+        token_config.decimals = mint_state.decimals;
+        token_config.total_minted = mint_state.supply as u128;
+    }
+
+    if config_account.data_len() < CONFIG_LEN {
+        let payer = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        check_signer(payer)?;
+
+        let shortfall = Rent::get()?.minimum_balance(CONFIG_LEN).saturating_sub(config_account.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, config_account.key, shortfall),
+                &[payer.clone(), config_account.clone(), system_program.clone()],
+            )?;
+            check_rent_exempt(payer)?;
+        }
+        msg!("Growing config {} from {} to {} bytes", config_account.key, config_account.data_len(), CONFIG_LEN);
+        config_account.realloc(CONFIG_LEN, false)?;
+    }
+    check_no_extra_accounts(account_info_iter)?;
+
+    token_config.migration_in_progress = false;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::ConfigMigrated { signer: *admin.key, old_version, new_version: CONFIG_VERSION }.emit()?;
+
+    msg!("Config {} migrated to version {}", config_account.key, CONFIG_VERSION);
+    Ok(())
+}
+
+// amount * bps / 10_000 in u128, the fee math behind swap quotes and mint rewards
+fn bps_of(amount: u64, bps: u16) -> Result<u64, ProgramError> {
+    math::mul_div(amount, bps as u64, 10_000)
+}
+
+// Constant-product quote with the fee taken off the input, shared by anything that prices a swap.
+// Intermediates are u128 so reserves near u64::MAX don't overflow before the division
+pub fn get_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<u64, ProgramError> {
+    if reserve_in == 0 || reserve_out == 0 || fee_bps > 10_000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let amount_in_after_fee = bps_of(amount_in, 10_000 - fee_bps)? as u128;
+    let numerator = (reserve_out as u128)
+        .checked_mul(amount_in_after_fee)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+    let denominator = (reserve_in as u128)
+        .checked_add(amount_in_after_fee)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+    let amount_out = numerator
+        .checked_div(denominator)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+
+    Ok(u64::try_from(amount_out).map_err(|_| CustomError::ArithmeticOverflow)?)
+}
+
+// Every handler reads the config through here, so a foreign or truncated account fails with a clear message
+// instead of an opaque deserialization error
+pub fn load_config(account: &AccountInfo, program_id: &Pubkey) -> Result<TokenConfig, ProgramError> {
+    if account.owner != program_id {
+        msg!("Config account {} is not owned by this program", account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = account.try_borrow_mut_data()?;
+    let min_len = match data.len() {
+        LEGACY_CONFIG_LEN => LEGACY_CONFIG_LEN,
+        _ => CONFIG_LEN,
+    };
+    if data.len() < min_len {
+        msg!("Config account {} is {} bytes, expected at least {}", account.key, data.len(), min_len);
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    // Read-only accounts can't take an in-place upgrade, so migrate a copy of their data
+    let token_config = if account.is_writable {
+        migrate(&mut data)?
+    } else {
+        migrate(&mut data.to_vec())?
+    };
+
+    // Only the PDA of the recorded mint can be a config, so a look-alike account owned by this program is rejected.
+    // Configs written before the PDA live at a keypair address, so they carry no bump and are let through
+    if token_config.initialized && token_config.bump != 0 {
+        let expected = Pubkey::create_program_address(
+            &[CONFIG_SEED, token_config.mint.as_ref(), &[token_config.bump]],
+            program_id,
+        ).map_err(|_| ProgramError::InvalidSeeds)?;
+        if *account.key != expected {
+            msg!("Config account {} is not the config PDA {} of mint {}", account.key, expected, token_config.mint);
+            return Err(ProgramError::InvalidSeeds);
+        }
+    }
+    Ok(token_config)
+}
+
+pub fn config_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED, mint.as_ref()], program_id)
+}
+
+// Handlers that take the mint alongside the config make sure it's the mint the config was created for
+fn check_config_mint(token_config: &TokenConfig, mint_account: &AccountInfo) -> ProgramResult {
+    if *mint_account.key != token_config.mint {
+        msg!("Mint {} is not governed by this config", mint_account.key);
+        return Err(CustomError::MintMismatch.into());
+    }
+    Ok(())
+}
+
+// Token accounts moved through under a config must hold its mint, whether or not the mint itself was passed
+fn check_account_mint(token_config: &TokenConfig, account: &AccountInfo, account_state: &Account) -> ProgramResult {
+    if account_state.mint != token_config.mint {
+        msg!("{} holds {} tokens, not the mint {} of this config", account.key, account_state.mint, token_config.mint);
+        return Err(CustomError::MintMismatch.into());
+    }
+    Ok(())
+}
+
+// Mint::unpack only says InvalidAccountData, so name the account and whether it isn't a mint at all
+// (a wrong account, or a Token-2022 mint with extensions) or a mint that was never initialized
+fn unpack_mint(mint_account: &AccountInfo) -> Result<Mint, ProgramError> {
+    let data = mint_account.try_borrow_data()?;
+    if data.len() != Mint::LEN {
+        msg!("{} is not a token mint: it holds {} bytes, a mint holds {}", mint_account.key, data.len(), Mint::LEN);
+        return Err(CustomError::InvalidMint.into());
+    }
+    Mint::unpack(&data).map_err(|_| {
+        msg!("Mint {} is not initialized", mint_account.key);
+        CustomError::InvalidMint.into()
+    })
+}
+
+// State-changing handlers refuse to run on a config that is halfway through a migration
+fn check_not_migrating(token_config: &TokenConfig) -> ProgramResult {
+    if token_config.migration_in_progress {
+        msg!("Config migration in progress, try again once it completes");
+        return Err(CustomError::MigrationInProgress.into());
+    }
+    Ok(())
+}
+
+// Decimals can't change on a real mint, so a disagreement with what initialize recorded means the wrong mint data
+fn check_config_decimals(token_config: &TokenConfig, mint_state: &Mint) -> ProgramResult {
+    if mint_state.decimals != token_config.decimals {
+        msg!("Mint has {} decimals, the config recorded {}", mint_state.decimals, token_config.decimals);
+        return Err(CustomError::DecimalsMismatch.into());
+    }
+    Ok(())
+}
+
+// This function here will deserialize the u64 amount from instruction data, for security reasons
+fn decode_amount(data: &[u8]) -> Result<u64, ProgramError> {
+    check_data_not_empty(data)?;
+    if data.len() != 8 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+    Ok(u64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?))
+}
+
+// LEB128 amount that must fill the data exactly. Overlong encodings (a trailing zero group) and anything
+// past u64::MAX are rejected, so every amount has exactly one valid encoding
+fn decode_amount_varint(data: &[u8]) -> Result<u64, ProgramError> {
+    let mut amount: u64 = 0;
+    for (i, byte) in data.iter().enumerate() {
+        let group = (byte & 0x7f) as u64;
+        // The tenth group only has room for the single remaining bit of a u64
+        if i == 9 && group > 1 {
+            return Err(CustomError::InvalidAmount.into());
+        }
+        amount |= group << (7 * i);
+
+        if byte & 0x80 == 0 {
+            if i + 1 != data.len() || (i > 0 && group == 0) {
+                return Err(CustomError::InvalidAmount.into());
+            }
+            return Ok(amount);
+        }
+        if i == 9 {
+            return Err(CustomError::InvalidAmount.into());
+        }
+    }
+    Err(CustomError::InvalidAmount.into())
+}
+
+fn decode_bps(data: &[u8]) -> Result<u16, ProgramError> {
+    check_data_len(data, 2)?;
+    let bps = u16::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    if bps > 10_000 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(bps)
+}
+
+fn decode_timestamp(data: &[u8]) -> Result<i64, ProgramError> {
+    check_data_len(data, 8)?;
+    Ok(i64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?))
+}
+
+fn decode_pubkey(data: &[u8]) -> Result<Pubkey, ProgramError> {
+    check_data_len(data, 32)?;
+    Ok(Pubkey::new_from_array(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?))
+}
+
+// A missing payload gets its own error, so integrators can tell it apart from one that is merely malformed
+fn check_data_not_empty(data: &[u8]) -> ProgramResult {
+    if data.is_empty() {
+        msg!("Instruction data is empty");
+        return Err(CustomError::EmptyInstructionData.into());
+    }
+    Ok(())
+}
+
+fn check_data_len(data: &[u8], expected: usize) -> ProgramResult {
+    check_data_not_empty(data)?;
+    if data.len() != expected {
+        msg!("Instruction data is {} bytes, expected {}", data.len(), expected);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+// Every admin-gated instruction goes through here, so the signature and key checks can't drift apart.
+// `what` finishes the "Only ..." log line, e.g. "the config admin can update the config"
+fn require_admin(admin: &AccountInfo, expected: &Pubkey, what: &str) -> ProgramResult {
+    check_signer(admin)?;
+    if admin.key != expected {
+        msg!("Unauthorized: Only {}.", what);
+        return Err(CustomError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+// Privileged changes outside UpdateConfig have no queued form, so while a timelock is set they are refused
+// outright rather than taking effect at once; the delay itself can only be lowered through the queue
+fn check_no_timelock(token_config: &TokenConfig) -> ProgramResult {
+    if token_config.timelock_delay > 0 {
+        msg!("Admin changes are disabled while a timelock of {}s is set", token_config.timelock_delay);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+    Ok(())
+}
+
+// This function here will check the signer for security reason
+fn check_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        msg!("Missing required signature for account: {}", account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+// Handlers that sign for a program PDA must only ever hand that signature to the real token program
+fn check_token_program(token_program: &AccountInfo) -> ProgramResult {
+    if *token_program.key != spl_token::id() {
+        msg!("{} is not the token program", token_program.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+// Checks the signer is the freeze authority recorded on the mint itself
+fn check_freeze_authority(mint_account: &AccountInfo, freeze_authority: &AccountInfo) -> ProgramResult {
+    check_signer(freeze_authority)?;
+
+    let mint_state = unpack_mint(mint_account)?;
+    match mint_state.freeze_authority {
+        COption::Some(authority) if authority == *freeze_authority.key => Ok(()),
+        COption::Some(authority) => {
+            msg!("Unauthorized: {} is not the freeze authority {}", freeze_authority.key, authority);
+            Err(CustomError::Unauthorized.into())
+        },
+        COption::None => {
+            msg!("Mint {} has no freeze authority set", mint_account.key);
+            Err(CustomError::NoFreezeAuthority.into())
+        },
+    }
+}
+
+// Creates a program-owned account at a PDA, with the payer covering rent. Anyone can send lamports to a PDA
+// before it exists, and create_account refuses an address that holds any, so a pre-funded PDA is topped up
+// to the rent-exempt minimum, then allocated and assigned instead
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    space: usize,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let rent = Rent::get()?;
+    let required_lamports = rent.minimum_balance(space);
+    let cpi_accounts = [payer.clone(), pda_account.clone(), system_program.clone()];
+
+    if pda_account.lamports() == 0 {
+        let create_ix = system_instruction::create_account(
+            payer.key,
+            pda_account.key,
+            required_lamports,
+            space as u64,
+            program_id,
+        );
+        invoke_signed(&create_ix, &cpi_accounts, &[seeds])?;
+    } else {
+        let top_up = required_lamports.saturating_sub(pda_account.lamports());
+        if top_up > 0 {
+            invoke(&system_instruction::transfer(payer.key, pda_account.key, top_up), &cpi_accounts)?;
+        }
+        invoke_signed(&system_instruction::allocate(pda_account.key, space as u64), &cpi_accounts, &[seeds])?;
+        invoke_signed(&system_instruction::assign(pda_account.key, program_id), &cpi_accounts, &[seeds])?;
+    }
+
+    check_rent_exempt(payer)?;
+    check_rent_exempt(pda_account)
+}
+
+// Run after a CPI that moved lamports out of an account that has to survive the instruction.
+// An account drained to zero counts as closed rather than under the rent floor
+fn check_rent_exempt(account: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    if lamports > 0 && !Rent::get()?.is_exempt(lamports, account.data_len()) {
+        msg!("{} would be left below the rent-exempt minimum with {} lamports", account.key, lamports);
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    Ok(())
+}
+
+// Vaults holding program-controlled tokens are owned by a PDA of the account they fund: the config for
+// the airdrop and a compounding fee treasury, or the vesting schedule, escrow or proposal itself. Returns the PDA's bump for signing
+fn check_vault_authority(vault_authority: &AccountInfo, scope: &Pubkey, program_id: &Pubkey) -> Result<u8, ProgramError> {
+    let (authority_address, authority_bump) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, scope.as_ref()], program_id);
+    if *vault_authority.key != authority_address {
+        msg!("Vault authority {} is not the program's PDA", vault_authority.key);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(authority_bump)
+}
+
+// The vault must be a real token account held by the given vault authority
+fn check_vault(vault: &AccountInfo, authority: &Pubkey) -> Result<Account, ProgramError> {
+    if *vault.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let vault_state = Account::unpack(&vault.try_borrow_data()?)?;
+    if vault_state.owner != *authority {
+        msg!("Vault {} is not owned by the program's vault authority {}", vault.key, authority);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(vault_state)
+}
+
+// Extra trailing accounts usually mean a client built the instruction for a different layout.
+// Only enforced with the strict-accounts feature, since production callers may append accounts freely
+#[cfg(feature = "strict-accounts")]
+fn check_no_extra_accounts(account_info_iter: &std::slice::Iter<AccountInfo>) -> ProgramResult {
+    if account_info_iter.len() > 0 {
+        msg!("{} unexpected trailing accounts", account_info_iter.len());
+        return Err(CustomError::TooManyAccounts.into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "strict-accounts"))]
+fn check_no_extra_accounts(_account_info_iter: &std::slice::Iter<AccountInfo>) -> ProgramResult {
+    Ok(())
+}
+
+// Sorted-pair keccak proof, so the client doesn't need to send left/right flags
+fn verify_merkle_proof(leaf: [u8; 32], proof: &[u8], root: &[u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof.chunks_exact(32) {
+        computed = if computed[..] <= node[..] {
+            keccak::hashv(&[&computed, node]).to_bytes()
+        } else {
+            keccak::hashv(&[node, &computed]).to_bytes()
+        };
+    }
+    computed == *root
+}
+
+// Catches the mint's freeze authority being changed out-of-band from what the config expects
+fn check_expected_freeze_authority(mint_state: &Mint, token_config: &TokenConfig) -> ProgramResult {
+    if token_config.expected_freeze_authority == Pubkey::default() {
+        return Ok(());
+    }
+    if mint_state.freeze_authority != COption::Some(token_config.expected_freeze_authority) {
+        msg!("Mint freeze authority has drifted from the expected {}", token_config.expected_freeze_authority);
+        return Err(CustomError::FreezeAuthorityMismatch.into());
+    }
+    Ok(())
+}
+
+// Adds a burn to the breaker's window. The burn that crosses the threshold still goes through, since a failed
+// instruction couldn't leave the flag raised; it is everything after it that gets held
+fn record_breaker_burn(token_config: &mut TokenConfig, amount: u64, signer: &Pubkey) -> ProgramResult {
+    if token_config.burn_breaker_threshold == 0 {
+        return Ok(());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if now.saturating_sub(token_config.burn_window_start_ts) >= token_config.burn_breaker_window {
+        token_config.burn_window_start_ts = now;
+        token_config.burned_in_window = 0;
+    }
+    token_config.burned_in_window = token_config.burned_in_window.saturating_add(amount);
+    if token_config.burned_in_window > token_config.burn_breaker_threshold {
+        msg!(
+            "{} burned since {} is over the breaker threshold of {}, pausing",
+            token_config.burned_in_window,
+            token_config.burn_window_start_ts,
+            token_config.burn_breaker_threshold
+        );
+        token_config.migration_in_progress = true;
+        AdminEvent::BurnBreakerTripped {
+            signer: *signer,
+            burned_in_window: token_config.burned_in_window,
+            window_start_ts: token_config.burn_window_start_ts,
+        }.emit()?;
+    }
+    Ok(())
+}
+
+// Adds amount to the current 24h window, starting a fresh window once the previous one has lapsed
+fn record_daily_mint(token_config: &mut TokenConfig, amount: u64) -> ProgramResult {
+    if token_config.daily_mint_limit == 0 {
+        return Ok(());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if now.saturating_sub(token_config.window_start_ts) >= MINT_WINDOW_SECONDS {
+        token_config.window_start_ts = now;
+        token_config.minted_in_window = 0;
+    }
+
+    let minted = math::checked_add(token_config.minted_in_window, amount)?;
+    if minted > token_config.daily_mint_limit {
+        msg!("Minting {} would exceed the daily limit of {}", amount, token_config.daily_mint_limit);
+        return Err(CustomError::DailyMintLimitExceeded.into());
+    }
+    token_config.minted_in_window = minted;
+    Ok(())
+}
+
+// Only reached with post-cap inflation enabled. Before the cap this is a normal cap check that records when the
+// cap gets hit; after it, amount must fit in the inflation accrued since then minus what was already minted
+fn check_hybrid_supply(token_config: &mut TokenConfig, supply: u64, amount: u64) -> ProgramResult {
+    let now = Clock::get()?.unix_timestamp;
+    if token_config.cap_reached_ts == 0 && supply >= token_config.max_supply {
+        token_config.cap_reached_ts = now;
+    }
+
+    if token_config.cap_reached_ts == 0 {
+        let new_supply = math::checked_add(supply, amount)?;
+        if new_supply > token_config.max_supply {
+            msg!("Minting would exceed max supply limit of {}", token_config.max_supply);
+            return Err(CustomError::MaxSupplyExceeded.into());
+        }
+        if new_supply == token_config.max_supply {
+            token_config.cap_reached_ts = now;
+        }
+        return Ok(());
+    }
+
+    let elapsed = now.saturating_sub(token_config.cap_reached_ts).max(0) as u128;
+    let accrued = (token_config.max_supply as u128)
+        .checked_mul(token_config.post_cap_annual_inflation_bps as u128)
+        .and_then(|scaled| scaled.checked_mul(elapsed))
+        .ok_or(CustomError::ArithmeticOverflow)?
+        / (10_000 * SECONDS_PER_YEAR as u128);
+    let accrued = u64::try_from(accrued).unwrap_or(u64::MAX);
+    let available = accrued.saturating_sub(token_config.post_cap_minted);
+    if amount > available {
+        msg!("Only {} tokens of post-cap inflation are available to mint", available);
+        return Err(CustomError::MaxSupplyExceeded.into());
+    }
+    token_config.post_cap_minted = math::checked_add(token_config.post_cap_minted, amount)?;
+    Ok(())
+}
+
+// The config admin and the mint's own authority can drift apart, which would otherwise fail deep inside the CPI
+fn check_mint_authority(mint_state: &Mint, mint_authority: &AccountInfo) -> ProgramResult {
+    match mint_state.mint_authority {
+        // The token program only lets an authority be removed, never restored, so this mint is capped for good
+        COption::None => {
+            msg!("Mint authority has been removed, minting is finalized");
+            Err(CustomError::MintingFinalized.into())
+        },
+        COption::Some(authority) if authority != *mint_authority.key => {
+            msg!("{} is not the mint authority of this mint", mint_authority.key);
+            Err(CustomError::UnauthorizedMint.into())
+        },
+        COption::Some(_) => Ok(()),
+    }
 }
 
-pub enum CustomError {
-    MaxSupplyExceeded = 0x1,
-    UnauthorizedMint = 0x2,
-    InvalidAmount = 0x3,
+// Counts distinct configured admin signers that signed this instruction, anywhere in the account list
+fn check_admin_threshold(token_config: &TokenConfig, accounts: &[AccountInfo]) -> ProgramResult {
+    if token_config.threshold <= 1 {
+        return Ok(());
+    }
+
+    let approvals = token_config.admin_signers
+        .iter()
+        .filter(|signer| **signer != Pubkey::default())
+        .filter(|signer| accounts.iter().any(|account| account.is_signer && account.key == *signer))
+        .count();
+
+    if approvals < token_config.threshold as usize {
+        msg!("Unauthorized: {} of {} required admin signatures", approvals, token_config.threshold);
+        return Err(CustomError::UnauthorizedMint.into());
+    }
+    Ok(())
+}
+
+// Like the admin signers, the cosigner can be anywhere in the accounts as long as it signed
+fn check_high_value_cosigner(token_config: &TokenConfig, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    if token_config.high_value_threshold == 0 || amount <= token_config.high_value_threshold {
+        return Ok(());
+    }
+
+    let cosigned = token_config.cosigner_pubkey != Pubkey::default()
+        && accounts.iter().any(|account| account.is_signer && *account.key == token_config.cosigner_pubkey);
+    if !cosigned {
+        msg!("Unauthorized: mints above {} need the cosigner's signature", token_config.high_value_threshold);
+        return Err(CustomError::UnauthorizedMint.into());
+    }
+    Ok(())
+}
+
+// The default wire format leads with a 1-byte instruction tag. With the wide-discriminator feature the tag
+// is a 4-byte little-endian u32 instead, e.g. transfer is [0, 0, 0, 0] followed by the same payload as
+// [0]. Tags keep their 1-byte values either way, so anything above 255 is rejected. Both parsers are always
+// built so each can be tested whichever one the feature selects
+fn split_discriminator(instruction_data: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+    if cfg!(feature = "wide-discriminator") {
+        split_wide_discriminator(instruction_data)
+    } else {
+        split_byte_discriminator(instruction_data)
+    }
+}
+
+fn split_byte_discriminator(instruction_data: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+    let (tag, rest_of_data) = instruction_data.split_first()
+        .ok_or(CustomError::EmptyInstructionData)?;
+    Ok((*tag, rest_of_data))
+}
+
+// Like split_byte_discriminator, this doesn't log, so the unit tests can run it outside the runtime
+fn split_wide_discriminator(instruction_data: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+    if instruction_data.is_empty() {
+        return Err(CustomError::EmptyInstructionData.into());
+    }
+    if instruction_data.len() < 4 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (tag_data, rest_of_data) = instruction_data.split_at(4);
+    let tag = u32::from_le_bytes(tag_data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let tag = u8::try_from(tag).map_err(|_| ProgramError::InvalidInstructionData)?;
+    Ok((tag, rest_of_data))
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let result = dispatch(program_id, accounts, instruction_data);
+
+    if let Err(error) = &result {
+        CustomError::print(error);
+    }
+    result
+}
+
+fn dispatch(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let (tag, rest_of_data) = split_discriminator(instruction_data)?;
+
+    // A varint-encoded amount is widened to the fixed 8 bytes here, so the handlers only ever see one encoding
+    let widened_amount;
+    let (instruction, rest_of_data) = if tag & VARINT_AMOUNT_FLAG != 0 {
+        let instruction = tag & !VARINT_AMOUNT_FLAG;
+        if instruction != 0 && instruction != 1 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        widened_amount = decode_amount_varint(rest_of_data)?.to_le_bytes();
+        (instruction, &widened_amount[..])
+    } else {
+        (tag, rest_of_data)
+    };
+
+    log_info!("Processing: {}", instruction_name(instruction));
+    match instruction {
+        0 => process_transfer(accounts, rest_of_data, program_id),
+        1 => process_mint(accounts, rest_of_data, program_id),
+        2 => process_burn(accounts, rest_of_data, program_id),
+        3 => process_initialize(accounts, rest_of_data, program_id, false),
+        4 => process_update_config(accounts, rest_of_data, program_id),
+        5 => process_batch_mint(accounts, rest_of_data, program_id),
+        6 => process_validate_batch(accounts, rest_of_data),
+        7 => process_revoke(accounts),
+        8 => process_get_config(accounts, program_id),
+        9 => process_approve(accounts, rest_of_data),
+        10 => process_initialize_pool(accounts, rest_of_data, program_id),
+        11 => process_freeze(accounts),
+        12 => process_thaw(accounts),
+        13 => process_batch_revoke(accounts),
+        14 => process_claim(accounts, rest_of_data, program_id),
+        15 => process_create_vesting(accounts, rest_of_data, program_id),
+        16 => process_release(accounts, program_id),
+        17 => process_attest_supply(accounts, program_id),
+        18 => process_wrap_sol(accounts, rest_of_data),
+        19 => process_unwrap_sol(accounts),
+        20 => process_set_admin_signers(accounts, rest_of_data, program_id),
+        21 => process_queue_action(accounts, rest_of_data, program_id),
+        22 => process_execute_action(accounts, program_id),
+        23 => process_quote_swap(accounts, rest_of_data),
+        24 => process_escrow_deposit(accounts, rest_of_data, program_id),
+        25 => process_escrow_release(accounts, program_id),
+        26 => process_transfer_with_memo(accounts, rest_of_data, program_id),
+        27 => process_advance_epoch(accounts, program_id),
+        28 => process_get_reserves(accounts, program_id),
+        29 => process_rotate_mint_authority(accounts, rest_of_data, program_id),
+        30 => process_opt_in(accounts, program_id),
+        31 => process_set_opt_in_exempt(accounts, rest_of_data, program_id),
+        32 => process_close_empty_account(accounts),
+        33 => process_set_whitelisted(accounts, rest_of_data, program_id),
+        34 => process_burn_and_reduce_cap(accounts, rest_of_data, program_id),
+        35 => process_get_supply_headroom(accounts, program_id),
+        36 => process_set_roles(accounts, rest_of_data, program_id),
+        37 => process_initialize(accounts, rest_of_data, program_id, true),
+        38 => process_transfer_all(accounts, program_id),
+        39 => process_create_proposal(accounts, rest_of_data, program_id),
+        40 => process_cast_vote(accounts, rest_of_data, program_id),
+        41 => process_execute_proposal(accounts, program_id),
+        42 => process_set_fee_exempt(accounts, rest_of_data, program_id),
+        43 => process_initialize_and_mint(accounts, rest_of_data, program_id),
+        44 => process_simulate_mint(accounts, rest_of_data, program_id),
+        45 => process_batch_burn(accounts, rest_of_data, program_id),
+        46 => process_expiring(accounts, rest_of_data, program_id),
+        47 => process_verify_config(accounts, program_id),
+        48 => process_get_config_size(),
+        49 => process_migrate_config(accounts, program_id),
+        50 => process_initialize_migration(accounts, rest_of_data, program_id),
+        51 => process_migrate_tokens(accounts, rest_of_data, program_id),
+        52 => process_set_exemptions_batch(accounts, rest_of_data, program_id),
+        53 => process_transfer_checked(accounts, rest_of_data, program_id),
+        54 => process_health_check(accounts, program_id),
+        55 => process_reclaim_vote(accounts, program_id),
+        56 => process_audit_supply(accounts, program_id),
+        57 => process_initialize_supply_history(accounts, program_id),
+        58 => process_get_supply_history(accounts, program_id),
+        59 => process_disable_mint(accounts, program_id),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// Readable name for a dispatch tag, for logs and error messages
+pub fn instruction_name(tag: u8) -> &'static str {
+    match tag {
+        0 => "Transfer",
+        1 => "Mint",
+        2 => "Burn",
+        3 => "Initialize",
+        4 => "UpdateConfig",
+        5 => "BatchMint",
+        6 => "ValidateBatch",
+        7 => "Revoke",
+        8 => "GetConfig",
+        9 => "Approve",
+        10 => "InitializePool",
+        11 => "Freeze",
+        12 => "Thaw",
+        13 => "BatchRevoke",
+        14 => "Claim",
+        15 => "CreateVesting",
+        16 => "Release",
+        17 => "AttestSupply",
+        18 => "WrapSol",
+        19 => "UnwrapSol",
+        20 => "SetAdminSigners",
+        21 => "QueueAction",
+        22 => "ExecuteAction",
+        23 => "QuoteSwap",
+        24 => "EscrowDeposit",
+        25 => "EscrowRelease",
+        26 => "TransferWithMemo",
+        27 => "AdvanceEpoch",
+        28 => "GetReserves",
+        29 => "RotateMintAuthority",
+        30 => "OptIn",
+        31 => "SetOptInExempt",
+        32 => "CloseEmptyAccount",
+        33 => "SetWhitelisted",
+        34 => "BurnAndReduceCap",
+        35 => "GetSupplyHeadroom",
+        36 => "SetRoles",
+        37 => "InitializeIdempotent",
+        38 => "TransferAll",
+        39 => "CreateProposal",
+        40 => "CastVote",
+        41 => "ExecuteProposal",
+        42 => "SetFeeExempt",
+        43 => "InitializeAndMint",
+        44 => "SimulateMint",
+        45 => "BatchBurn",
+        46 => "Expiring",
+        47 => "VerifyConfig",
+        48 => "GetConfigSize",
+        49 => "MigrateConfig",
+        50 => "InitializeMigration",
+        51 => "MigrateTokens",
+        52 => "SetExemptionsBatch",
+        53 => "TransferChecked",
+        54 => "HealthCheck",
+        55 => "ReclaimVote",
+        56 => "AuditSupply",
+        57 => "InitializeSupplyHistory",
+        58 => "GetSupplyHistory",
+        59 => "DisableMint",
+        _ => "Unknown",
+    }
+}
+
+// Data is valid_until_slot(8) followed by a complete inner instruction, which only runs up to that slot
+fn process_expiring(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(rest_of_data)?;
+    if rest_of_data.len() < 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (slot_data, inner_data) = rest_of_data.split_at(8);
+    let valid_until_slot = u64::from_le_bytes(slot_data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+
+    let slot = Clock::get()?.slot;
+    if slot > valid_until_slot {
+        msg!("Instruction expired at slot {}, it is slot {}", valid_until_slot, slot);
+        return Err(CustomError::InstructionExpired.into());
+    }
+
+    dispatch(program_id, accounts, inner_data)
+}
+
+// Accounts are config, mint, then payer and system program when the config still has to be created. The mint
+// authority must sign, as the payer or as any trailing account. The idempotent variant treats a retry against
+// an identical existing config as success
+fn process_initialize(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey, idempotent: bool) -> ProgramResult {
+    // admin(32) + max_supply(8)
+    check_data_len(rest_of_data, 40)?;
+
+    let admin_pubkey = decode_pubkey(&rest_of_data[..32])?;
+    let max_supply = u64::from_le_bytes(
+        rest_of_data[32..40].try_into().map_err(|_| ProgramError::InvalidInstructionData)?
+    );
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+
+    // unpack_mint fails on a mint that was never initialized
+    if *mint_account.owner != spl_token::id() {
+        msg!("Mint {} is not owned by the token program", mint_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mint_state = unpack_mint(mint_account)?;
+
+    // Whoever initializes the config picks its admin, so only the mint's own authority may do it
+    match mint_state.mint_authority {
+        COption::Some(authority) if accounts.iter().any(|account| account.is_signer && *account.key == authority) => {},
+        _ => {
+            msg!("Unauthorized: the mint authority of {} must sign to initialize its config", mint_account.key);
+            return Err(CustomError::UnauthorizedMint.into());
+        },
+    }
+
+    // The config is always the PDA of [CONFIG_SEED, mint], so there is exactly one config per mint.
+    // When it doesn't exist yet the program creates it, paid for by the trailing payer account
+    let (config_pda, config_bump) = config_address(program_id, mint_account.key);
+    if *config_account.key != config_pda {
+        msg!("Config account {} is not the expected PDA {}", config_account.key, config_pda);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if config_account.data_is_empty() {
+        let payer = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        check_signer(payer)?;
+        create_pda_account(
+            payer,
+            config_account,
+            system_program,
+            program_id,
+            CONFIG_LEN,
+            &[CONFIG_SEED, mint_account.key.as_ref(), &[config_bump]],
+        )?;
+    }
+
+    // Ensure account size is enough for TokenConfig
+    if config_account.data_len() < CONFIG_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // A config that isn't rent-exempt can be garbage-collected, taking the admin with it
+    let rent = Rent::get()?;
+    if !rent.is_exempt(config_account.lamports(), config_account.data_len()) {
+        msg!("Config account {} is not rent-exempt", config_account.key);
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    if token_config.initialized {
+        if idempotent && token_config.admin_pubkey == admin_pubkey && token_config.max_supply == max_supply {
+            msg!("Config {} is already initialized with the same parameters", config_account.key);
+            return Ok(());
+        }
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    // Only the initialized flag was checked above, so make sure no stale field from an earlier write survives
+    if config_account.try_borrow_data()?.iter().any(|byte| *byte != 0) {
+        msg!("Config account {} is not initialized but holds leftover data", config_account.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    token_config.version = CONFIG_VERSION;
+    token_config.admin_pubkey = admin_pubkey;
+    token_config.max_supply = max_supply;
+    token_config.bump = config_bump;
+    token_config.mint = *mint_account.key;
+    token_config.decimals = mint_state.decimals;
+    token_config.total_minted = mint_state.supply as u128;
+    token_config.initialized = true;
+
+    // Oversized accounts get everything past the struct cleared so later reads never see stale bytes
+    let serialized = token_config.try_to_vec()?;
+    let mut config_data = config_account.try_borrow_mut_data()?;
+    config_data[..serialized.len()].copy_from_slice(&serialized);
+    config_data[serialized.len()..].fill(0);
+
+    msg!(
+        "Token initialized with admin: {}, max supply: {} and {} decimals",
+        admin_pubkey,
+        max_supply,
+        mint_state.decimals
+    );
+    Ok(())
+}
+
+fn process_update_config(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let (field, value) = rest_of_data.split_first()
+        .ok_or(CustomError::EmptyInstructionData)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    if !token_config.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    require_admin(admin, &token_config.config_admin(), "the config admin can update the config")?;
+
+    if token_config.timelock_delay > 0 {
+        msg!("Config changes must be queued while a timelock of {}s is set", token_config.timelock_delay);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+
+    apply_config_update_logged(&mut token_config, *field, value, admin.key)?;
+
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+fn process_queue_action(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let (field, value) = rest_of_data.split_first()
+        .ok_or(CustomError::EmptyInstructionData)?;
+    if value.len() > 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.config_admin(), "the config admin can queue config changes")?;
+
+    let mut padded = [0u8; 32];
+    padded[..value.len()].copy_from_slice(value);
+    let earliest_exec_ts = Clock::get()?.unix_timestamp
+        .checked_add(token_config.timelock_delay)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+
+    // Queuing replaces whatever was pending before
+    token_config.pending_action = PendingAction {
+        queued: true,
+        field: *field,
+        value_len: value.len() as u8,
+        value: padded,
+        earliest_exec_ts,
+    };
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    AdminEvent::ActionQueued { signer: *admin.key, field: *field, value: value.to_vec(), earliest_exec_ts }.emit()?;
+
+    msg!("Config change {} queued, executable from {}", field, earliest_exec_ts);
+    Ok(())
+}
+
+fn process_execute_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.config_admin(), "the config admin can execute config changes")?;
+
+    let pending = std::mem::take(&mut token_config.pending_action);
+    if !pending.queued {
+        msg!("No config change is queued");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if now < pending.earliest_exec_ts {
+        msg!("Queued config change can't run until {}, it is {}", pending.earliest_exec_ts, now);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+
+    let value = pending.value
+        .get(..pending.value_len as usize)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    apply_config_update_logged(&mut token_config, pending.field, value, admin.key)?;
+
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+// The current value of an UpdateConfig field, encoded the way UpdateConfig takes it
+fn config_field_value(token_config: &TokenConfig, field: u8) -> Result<Vec<u8>, ProgramError> {
+    let value = match field {
+        0 => token_config.max_mint_operations.to_le_bytes().to_vec(),
+        1 => token_config.mint_rewards_bps.to_le_bytes().to_vec(),
+        2 => token_config.rewards_vault.to_bytes().to_vec(),
+        3 => token_config.merkle_root.to_vec(),
+        4 => token_config.expected_freeze_authority.to_bytes().to_vec(),
+        5 => token_config.min_mint_amount.to_le_bytes().to_vec(),
+        6 => token_config.max_transfer_amount.to_le_bytes().to_vec(),
+        7 => token_config.timelock_delay.to_le_bytes().to_vec(),
+        8 => token_config.transfers_unlocked_ts.to_le_bytes().to_vec(),
+        9 => token_config.transfer_fee_bps.to_le_bytes().to_vec(),
+        10 => token_config.fee_treasury.to_bytes().to_vec(),
+        11 => token_config.daily_mint_limit.to_le_bytes().to_vec(),
+        12 => vec![token_config.require_opt_in as u8],
+        13 => vec![token_config.whitelist_mode as u8],
+        14 => token_config.quorum_votes.to_le_bytes().to_vec(),
+        15 => token_config.sol_fee_lamports.to_le_bytes().to_vec(),
+        16 => token_config.sol_fee_treasury.to_bytes().to_vec(),
+        17 => token_config.post_cap_annual_inflation_bps.to_le_bytes().to_vec(),
+        18 => token_config.high_value_threshold.to_le_bytes().to_vec(),
+        19 => token_config.cosigner_pubkey.to_bytes().to_vec(),
+        20 => token_config.per_account_mint_cap.to_le_bytes().to_vec(),
+        21 => token_config.burn_treasury_bps.to_le_bytes().to_vec(),
+        22 => token_config.burn_treasury.to_bytes().to_vec(),
+        23 => vec![token_config.migration_in_progress as u8],
+        24 => vec![token_config.require_checked_transfers as u8],
+        25 => token_config.airdrop_vault.to_bytes().to_vec(),
+        26 => token_config.max_accounts_per_owner.to_le_bytes().to_vec(),
+        27 => token_config.compound_threshold.to_le_bytes().to_vec(),
+        28 => token_config.yield_vault.to_bytes().to_vec(),
+        29 => token_config.burn_breaker_threshold.to_le_bytes().to_vec(),
+        30 => token_config.burn_breaker_window.to_le_bytes().to_vec(),
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+    Ok(value)
+}
+
+// Applies the change and emits the matching ConfigUpdated event
+fn apply_config_update_logged(token_config: &mut TokenConfig, field: u8, value: &[u8], signer: &Pubkey) -> ProgramResult {
+    let old_value = config_field_value(token_config, field)?;
+    apply_config_update(token_config, field, value)?;
+    AdminEvent::ConfigUpdated {
+        signer: *signer,
+        field,
+        old_value,
+        new_value: config_field_value(token_config, field)?,
+    }.emit()
+}
+
+// Each field gets its own tag so new settings can be added without changing the layout of the others
+fn apply_config_update(token_config: &mut TokenConfig, field: u8, value: &[u8]) -> ProgramResult {
+    match field {
+        0 => {
+            token_config.max_mint_operations = decode_amount(value)?;
+            msg!("Max mint operations set to {}", token_config.max_mint_operations);
+        },
+        1 => {
+            token_config.mint_rewards_bps = decode_bps(value)?;
+            msg!("Mint rewards set to {} bps", token_config.mint_rewards_bps);
+        },
+        2 => {
+            token_config.rewards_vault = decode_pubkey(value)?;
+            msg!("Rewards vault set to {}", token_config.rewards_vault);
+        },
+        3 => {
+            token_config.merkle_root = value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+            msg!("Airdrop merkle root updated");
+        },
+        4 => {
+            token_config.expected_freeze_authority = decode_pubkey(value)?;
+            msg!("Expected freeze authority set to {}", token_config.expected_freeze_authority);
+        },
+        5 => {
+            token_config.min_mint_amount = decode_amount(value)?;
+            msg!("Min mint amount set to {}", token_config.min_mint_amount);
+        },
+        6 => {
+            token_config.max_transfer_amount = decode_amount(value)?;
+            msg!("Max transfer amount set to {}", token_config.max_transfer_amount);
+        },
+        7 => {
+            token_config.timelock_delay = decode_timestamp(value)?;
+            if token_config.timelock_delay < 0 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            msg!("Timelock delay set to {}s", token_config.timelock_delay);
+        },
+        8 => {
+            token_config.transfers_unlocked_ts = decode_timestamp(value)?;
+            msg!("Transfers unlock at {}", token_config.transfers_unlocked_ts);
+        },
+        9 => {
+            token_config.transfer_fee_bps = decode_bps(value)?;
+            msg!("Transfer fee set to {} bps", token_config.transfer_fee_bps);
+        },
+        10 => {
+            token_config.fee_treasury = decode_pubkey(value)?;
+            msg!("Fee treasury set to {}", token_config.fee_treasury);
+        },
+        11 => {
+            token_config.daily_mint_limit = decode_amount(value)?;
+            msg!("Daily mint limit set to {}", token_config.daily_mint_limit);
+        },
+        12 => {
+            token_config.require_opt_in = match value {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            msg!("Recipient opt-in required: {}", token_config.require_opt_in);
+        },
+        13 => {
+            token_config.whitelist_mode = match value {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            msg!("Whitelist mode: {}", token_config.whitelist_mode);
+        },
+        14 => {
+            token_config.quorum_votes = decode_amount(value)?;
+            msg!("Governance quorum set to {} votes", token_config.quorum_votes);
+        },
+        15 => {
+            token_config.sol_fee_lamports = decode_amount(value)?;
+            msg!("SOL transfer fee set to {} lamports", token_config.sol_fee_lamports);
+        },
+        16 => {
+            token_config.sol_fee_treasury = decode_pubkey(value)?;
+            msg!("SOL fee treasury set to {}", token_config.sol_fee_treasury);
+        },
+        17 => {
+            token_config.post_cap_annual_inflation_bps = decode_bps(value)?;
+            msg!("Post-cap inflation set to {} bps a year", token_config.post_cap_annual_inflation_bps);
+        },
+        18 => {
+            token_config.high_value_threshold = decode_amount(value)?;
+            msg!("High-value mint threshold set to {}", token_config.high_value_threshold);
+        },
+        19 => {
+            token_config.cosigner_pubkey = decode_pubkey(value)?;
+            msg!("High-value mint cosigner set to {}", token_config.cosigner_pubkey);
+        },
+        20 => {
+            token_config.per_account_mint_cap = decode_amount(value)?;
+            msg!("Per-account mint cap set to {}", token_config.per_account_mint_cap);
+        },
+        21 => {
+            token_config.burn_treasury_bps = decode_bps(value)?;
+            msg!("Burn treasury share set to {} bps", token_config.burn_treasury_bps);
+        },
+        22 => {
+            token_config.burn_treasury = decode_pubkey(value)?;
+            msg!("Burn treasury set to {}", token_config.burn_treasury);
+        },
+        23 => {
+            token_config.migration_in_progress = match value {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            msg!("Config migration in progress: {}", token_config.migration_in_progress);
+        },
+        24 => {
+            token_config.require_checked_transfers = match value {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            msg!("Checked transfers required: {}", token_config.require_checked_transfers);
+        },
+        25 => {
+            token_config.airdrop_vault = decode_pubkey(value)?;
+            msg!("Airdrop vault set to {}", token_config.airdrop_vault);
+        },
+        26 => {
+            token_config.max_accounts_per_owner = decode_amount(value)?;
+            msg!("Per-owner account limit set to {}", token_config.max_accounts_per_owner);
+        },
+        27 => {
+            token_config.compound_threshold = decode_amount(value)?;
+            msg!("Fee compounding threshold set to {}", token_config.compound_threshold);
+        },
+        28 => {
+            token_config.yield_vault = decode_pubkey(value)?;
+            msg!("Yield vault set to {}", token_config.yield_vault);
+        },
+        29 => {
+            token_config.burn_breaker_threshold = decode_amount(value)?;
+            msg!("Burn breaker threshold set to {}", token_config.burn_breaker_threshold);
+        },
+        30 => {
+            token_config.burn_breaker_window = decode_timestamp(value)?;
+            if token_config.burn_breaker_window < 0 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            msg!("Burn breaker window set to {} seconds", token_config.burn_breaker_window);
+        },
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+    Ok(())
+}
+
+// Data is the threshold followed by up to MAX_ADMIN_SIGNERS pubkeys; changing them needs the current threshold
+fn process_set_admin_signers(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let (threshold, signer_data) = rest_of_data.split_first()
+        .ok_or(CustomError::EmptyInstructionData)?;
+    let signer_chunks = signer_data.chunks_exact(32);
+    if !signer_chunks.remainder().is_empty() || signer_chunks.len() > MAX_ADMIN_SIGNERS {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if *threshold as usize > signer_chunks.len() {
+        msg!("Threshold {} is higher than the {} signers given", threshold, signer_chunks.len());
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut admin_signers = [Pubkey::default(); MAX_ADMIN_SIGNERS];
+    for (i, chunk) in signer_chunks.enumerate() {
+        let signer = decode_pubkey(chunk)?;
+        if signer == Pubkey::default() || admin_signers[..i].contains(&signer) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        admin_signers[i] = signer;
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.admin_pubkey, "the admin can change the admin signers")?;
+    check_no_timelock(&token_config)?;
+    check_admin_threshold(&token_config, accounts)?;
+
+    let old_threshold = token_config.threshold;
+    token_config.admin_signers = admin_signers;
+    token_config.threshold = *threshold;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::AdminSignersChanged { signer: *admin.key, old_threshold, new_threshold: *threshold }.emit()?;
+
+    msg!("Admin signers updated with a threshold of {}", threshold);
+    Ok(())
+}
+
+// Data is admin(32) + max_supply(8) + initial_supply(8). Accounts are config, mint, admin, destination,
+// token_program, then payer and system_program when the config still has to be created
+fn process_initialize_and_mint(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(rest_of_data, 48)?;
+    let (init_data, supply_data) = rest_of_data.split_at(40);
+    let admin_pubkey = decode_pubkey(&init_data[..32])?;
+    let max_supply = u64::from_le_bytes(
+        init_data[32..40].try_into().map_err(|_| ProgramError::InvalidInstructionData)?
+    );
+    let initial_supply = decode_amount(supply_data)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let creation_accounts = account_info_iter.as_slice();
+
+    // Everything the mint half checks is validated up front, so nothing is created for a launch that can't finish
+    check_signer(admin)?;
+    if *admin.key != admin_pubkey {
+        msg!("Unauthorized: {} is not the admin being initialized", admin.key);
+        return Err(CustomError::UnauthorizedMint.into());
+    }
+    if initial_supply == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+    let mint_state = unpack_mint(mint_account)?;
+    check_mint_authority(&mint_state, admin)?;
+    let new_supply = math::checked_add(mint_state.supply, initial_supply)?;
+    if max_supply != UNLIMITED_SUPPLY && new_supply > max_supply {
+        msg!("Initial supply of {} would exceed max supply limit of {}", initial_supply, max_supply);
+        return Err(CustomError::MaxSupplyExceeded.into());
+    }
+
+    // The admin rides along at the end as the mint authority's signature, after whatever creation accounts there are
+    let init_accounts = [&[config_account.clone(), mint_account.clone()], creation_accounts, std::slice::from_ref(admin)].concat();
+    process_initialize(&init_accounts, init_data, program_id, false)?;
+
+    let mint_accounts = [
+        mint_account.clone(),
+        destination_account.clone(),
+        admin.clone(),
+        token_program.clone(),
+        config_account.clone(),
+    ];
+    process_mint(&mint_accounts, supply_data, program_id)
+}
+
+// Moves the mint authority and the mint admin together, so minting keeps working under the new key
+fn process_rotate_mint_authority(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let new_authority = decode_pubkey(rest_of_data)?;
+    if new_authority == Pubkey::default() {
+        msg!("New mint authority can't be the default pubkey");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.mint_admin(), "the mint admin can rotate the mint authority")?;
+    check_no_timelock(&token_config)?;
+    check_admin_threshold(&token_config, accounts)?;
+
+    check_config_mint(&token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+    check_mint_authority(&mint_state, admin)?;
+
+    let set_authority_ix = set_authority(
+        token_program.key,
+        mint_account.key,
+        Some(&new_authority),
+        AuthorityType::MintTokens,
+        admin.key,
+        &[],
+    )?;
+    invoke(&set_authority_ix, accounts)?;
+
+    AdminEvent::MintAuthorityRotated {
+        signer: *admin.key,
+        old_authority: *admin.key,
+        new_authority,
+    }.emit()?;
+
+    // The mint role follows the mint authority; admin_pubkey keeps the roles and admin signers either way
+    token_config.mint_admin = new_authority;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Mint authority and mint admin rotated to {}", new_authority);
+    Ok(())
+}
+
+// Removes the mint authority for good, so the supply is fixed at whatever the mint holds now. Accounts are config,
+// mint, mint admin and token program; the same guards as a rotation apply, since neither can be undone
+fn process_disable_mint(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.mint_admin(), "the mint admin can disable minting")?;
+    check_no_timelock(&token_config)?;
+    check_admin_threshold(&token_config, accounts)?;
+    check_token_program(token_program)?;
+
+    check_config_mint(&token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+    check_mint_authority(&mint_state, admin)?;
+
+    let set_authority_ix = set_authority(
+        token_program.key,
+        mint_account.key,
+        None,
+        AuthorityType::MintTokens,
+        admin.key,
+        &[],
+    )?;
+    invoke(&set_authority_ix, accounts)?;
+
+    token_config.mint_permanently_disabled = true;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::SupplyFrozen { signer: *admin.key, final_supply: mint_state.supply }.emit()?;
+
+    msg!("Minting permanently disabled at a supply of {}", mint_state.supply);
+    Ok(())
+}
+
+// Data is param(1) + new_value(8) + voting_period(8, seconds); any signer can propose and pays for the account
+fn process_create_proposal(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(rest_of_data, 17)?;
+    let param = rest_of_data[0];
+    if param != PROPOSAL_PARAM_MAX_SUPPLY {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let new_value = decode_amount(&rest_of_data[1..9])?;
+    let voting_period = decode_timestamp(&rest_of_data[9..])?;
+    if voting_period <= 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let proposer = next_account_info(account_info_iter)?;
+    let proposal_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(proposer)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    if token_config.quorum_votes == 0 {
+        msg!("Governance is disabled for this token");
+        return Err(CustomError::Unauthorized.into());
+    }
+
+    let id = token_config.proposal_count;
+    let (proposal_address, proposal_bump) = Pubkey::find_program_address(
+        &[PROPOSAL_SEED, config_account.key.as_ref(), &id.to_le_bytes()],
+        program_id,
+    );
+    if *proposal_account.key != proposal_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let end_ts = Clock::get()?.unix_timestamp
+        .checked_add(voting_period)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+    let proposal = Proposal {
+        id,
+        param,
+        new_value,
+        end_ts,
+        ..Proposal::default()
+    };
+    let serialized = proposal.try_to_vec()?;
+
+    create_pda_account(
+        proposer,
+        proposal_account,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[PROPOSAL_SEED, config_account.key.as_ref(), &id.to_le_bytes(), &[proposal_bump]],
+    )?;
+    proposal_account.try_borrow_mut_data()?.copy_from_slice(&serialized);
+
+    token_config.proposal_count = math::checked_add(id, 1)?;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Proposal {} created, voting closes at {}", id, end_ts);
+    Ok(())
+}
+
+fn load_proposal(proposal_account: &AccountInfo, config_account: &AccountInfo, program_id: &Pubkey) -> Result<Proposal, ProgramError> {
+    if proposal_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let proposal = Proposal::try_from_slice(&proposal_account.try_borrow_data()?)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let (proposal_address, _) = Pubkey::find_program_address(
+        &[PROPOSAL_SEED, config_account.key.as_ref(), &proposal.id.to_le_bytes()],
+        program_id,
+    );
+    if *proposal_account.key != proposal_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(proposal)
+}
+
+// Data is a single 0/1 support byte. The voter's whole balance is the weight, and is locked in the proposal's vote
+// vault until voting closes, so the same tokens can't be moved to another wallet and counted again. Each wallet and
+// each token account votes once per proposal
+fn process_cast_vote(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(rest_of_data)?;
+    let support = match rest_of_data {
+        [0] => false,
+        [1] => true,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let proposal_account = next_account_info(account_info_iter)?;
+    let voter = next_account_info(account_info_iter)?;
+    let voter_token_account = next_account_info(account_info_iter)?;
+    let vote_record = next_account_info(account_info_iter)?;
+    let account_record = next_account_info(account_info_iter)?;
+    let vote_vault = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(voter)?;
+    check_token_program(token_program)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    let mut proposal = load_proposal(proposal_account, config_account, program_id)?;
+
+    if Clock::get()?.unix_timestamp >= proposal.end_ts {
+        msg!("Voting on proposal {} closed at {}", proposal.id, proposal.end_ts);
+        return Err(CustomError::VotingClosed.into());
+    }
+
+    let voter_state = Account::unpack(&voter_token_account.try_borrow_data()?)?;
+    if voter_state.mint != token_config.mint {
+        return Err(CustomError::MintMismatch.into());
+    }
+    if voter_state.owner != *voter.key {
+        msg!("{} is not the owner of {}", voter.key, voter_token_account.key);
+        return Err(CustomError::Unauthorized.into());
+    }
+    let weight = voter_state.amount;
+    if weight == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let (authority_address, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, proposal_account.key.as_ref()], program_id);
+    let vault_state = check_vault(vote_vault, &authority_address)?;
+    if vault_state.mint != token_config.mint {
+        return Err(CustomError::MintMismatch.into());
+    }
+
+    let (record_address, record_bump) = Pubkey::find_program_address(
+        &[VOTE_SEED, proposal_account.key.as_ref(), voter.key.as_ref()],
+        program_id,
+    );
+    if *vote_record.key != record_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !vote_record.data_is_empty() {
+        msg!("{} has already voted on proposal {}", voter.key, proposal.id);
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    let (account_record_address, account_record_bump) = Pubkey::find_program_address(
+        &[VOTE_ACCOUNT_SEED, proposal_account.key.as_ref(), voter_token_account.key.as_ref()],
+        program_id,
+    );
+    if *account_record.key != account_record_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !account_record.data_is_empty() {
+        msg!("{} has already voted on proposal {}", voter_token_account.key, proposal.id);
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let serialized = VoteRecord {
+        support,
+        weight,
+        token_account: *voter_token_account.key,
+        reclaimed: false,
+    }.try_to_vec()?;
+    create_pda_account(
+        voter,
+        vote_record,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[VOTE_SEED, proposal_account.key.as_ref(), voter.key.as_ref(), &[record_bump]],
+    )?;
+    vote_record.try_borrow_mut_data()?.copy_from_slice(&serialized);
+
+    // The token account's record only needs to exist; it holds the wallet that voted with it
+    create_pda_account(
+        voter,
+        account_record,
+        system_program,
+        program_id,
+        32,
+        &[VOTE_ACCOUNT_SEED, proposal_account.key.as_ref(), voter_token_account.key.as_ref(), &[account_record_bump]],
+    )?;
+    account_record.try_borrow_mut_data()?.copy_from_slice(voter.key.as_ref());
+
+    let tally = if support { &mut proposal.yes_votes } else { &mut proposal.no_votes };
+    *tally = math::checked_add(*tally, weight)?;
+    proposal.serialize(&mut &mut proposal_account.try_borrow_mut_data()?[..])?;
+
+    msg!("{} voted {} on proposal {} with weight {}", voter.key, if support { "yes" } else { "no" }, proposal.id, weight);
+    let lock_ix = transfer(
+        token_program.key,
+        voter_token_account.key,
+        vote_vault.key,
+        voter.key,
+        &[],
+        weight,
+    )?;
+    invoke(&lock_ix, accounts)?;
+    Ok(())
+}
+
+// Returns a voter's locked tokens to the account they voted with, once voting on the proposal has closed
+fn process_reclaim_vote(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let proposal_account = next_account_info(account_info_iter)?;
+    let voter = next_account_info(account_info_iter)?;
+    let vote_record = next_account_info(account_info_iter)?;
+    let vote_vault = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(voter)?;
+    check_token_program(token_program)?;
+
+    check_not_migrating(&load_config(config_account, program_id)?)?;
+    let proposal = load_proposal(proposal_account, config_account, program_id)?;
+    if Clock::get()?.unix_timestamp < proposal.end_ts {
+        msg!("Votes on proposal {} stay locked until {}", proposal.id, proposal.end_ts);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+
+    let (record_address, _) = Pubkey::find_program_address(
+        &[VOTE_SEED, proposal_account.key.as_ref(), voter.key.as_ref()],
+        program_id,
+    );
+    if *vote_record.key != record_address || vote_record.owner != program_id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut record_data = vote_record.try_borrow_mut_data()?;
+    let mut record = VoteRecord::try_from_slice(&record_data).map_err(|_| ProgramError::InvalidAccountData)?;
+    if record.reclaimed {
+        msg!("{} has already reclaimed its vote on proposal {}", voter.key, proposal.id);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if *destination_account.key != record.token_account {
+        msg!("Locked tokens go back to {}", record.token_account);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let authority_bump = check_vault_authority(vault_authority, proposal_account.key, program_id)?;
+    check_vault(vote_vault, vault_authority.key)?;
+
+    record.reclaimed = true;
+    record.serialize(&mut &mut record_data[..])?;
+    drop(record_data);
+
+    msg!("Returning {} locked tokens to {}", record.weight, destination_account.key);
+    let transfer_ix = transfer(
+        token_program.key,
+        vote_vault.key,
+        destination_account.key,
+        vault_authority.key,
+        &[],
+        record.weight,
+    )?;
+    invoke_signed(
+        &transfer_ix,
+        accounts,
+        &[&[VAULT_AUTHORITY_SEED, proposal_account.key.as_ref(), &[authority_bump]]],
+    )?;
+    Ok(())
+}
+
+// Anyone can execute once voting has closed; the mint is needed so the cap never drops below circulating supply,
+// and the supply history records the change
+fn process_execute_proposal(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let proposal_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let history_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    let mut proposal = load_proposal(proposal_account, config_account, program_id)?;
+
+    if proposal.executed {
+        msg!("Proposal {} has already been executed", proposal.id);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if Clock::get()?.unix_timestamp < proposal.end_ts {
+        msg!("Voting on proposal {} is open until {}", proposal.id, proposal.end_ts);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+
+    let total_votes = math::checked_add(proposal.yes_votes, proposal.no_votes)?;
+    if total_votes < token_config.quorum_votes || proposal.yes_votes <= proposal.no_votes {
+        msg!("Proposal {} did not pass: {} yes, {} no, quorum {}", proposal.id, proposal.yes_votes, proposal.no_votes, token_config.quorum_votes);
+        return Err(CustomError::ProposalNotPassed.into());
+    }
+
+    check_config_mint(&token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+    if proposal.new_value < mint_state.supply {
+        msg!("New max supply {} is below the current supply {}", proposal.new_value, mint_state.supply);
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    AdminEvent::SupplyCapChanged {
+        signer: *proposal_account.key,
+        old_max_supply: token_config.max_supply,
+        new_max_supply: proposal.new_value,
+    }.emit()?;
+    record_supply_change(
+        history_account,
+        config_account,
+        program_id,
+        proposal_account.key,
+        token_config.max_supply,
+        proposal.new_value,
+    )?;
+    token_config.max_supply = proposal.new_value;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    proposal.executed = true;
+    proposal.serialize(&mut &mut proposal_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Proposal {} executed, max supply set to {}", proposal.id, token_config.max_supply);
+    Ok(())
+}
+
+// Data is mint_admin(32) + config_admin(32); only the top-level admin can hand out roles.
+// Passing the default pubkey for a role hands it back to the admin
+fn process_set_roles(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(rest_of_data, 64)?;
+    let mint_admin = decode_pubkey(&rest_of_data[..32])?;
+    let config_admin = decode_pubkey(&rest_of_data[32..])?;
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.admin_pubkey, "the admin can assign roles")?;
+    check_no_timelock(&token_config)?;
+    check_admin_threshold(&token_config, accounts)?;
+
+    let old_mint_admin = token_config.mint_admin();
+    let old_config_admin = token_config.config_admin();
+    token_config.mint_admin = mint_admin;
+    token_config.config_admin = config_admin;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::RolesChanged {
+        signer: *admin.key,
+        old_mint_admin,
+        new_mint_admin: token_config.mint_admin(),
+        old_config_admin,
+        new_config_admin: token_config.config_admin(),
+    }.emit()?;
+
+    msg!("Roles set: mint admin {}, config admin {}", token_config.mint_admin(), token_config.config_admin());
+    Ok(())
+}
+
+// Data is up to MAX_FEE_EXEMPT token account pubkeys, replacing the whole list; empty data clears it
+fn process_set_fee_exempt(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let chunks = rest_of_data.chunks_exact(32);
+    if !chunks.remainder().is_empty() || chunks.len() > MAX_FEE_EXEMPT {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let mut fee_exempt = [Pubkey::default(); MAX_FEE_EXEMPT];
+    for (slot, chunk) in fee_exempt.iter_mut().zip(chunks) {
+        *slot = decode_pubkey(chunk)?;
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.config_admin(), "the config admin can change fee exemptions")?;
+    check_no_timelock(&token_config)?;
+
+    let old_exempt = token_config.fee_exempt;
+    token_config.fee_exempt = fee_exempt;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::FeeExemptChanged { signer: *admin.key, old_exempt, new_exempt: fee_exempt }.emit()?;
+
+    msg!("Transfer fee exemptions updated");
+    Ok(())
+}
+
+// Data is exempt(1) followed by up to MAX_FEE_EXEMPT token account pubkeys. With exempt set to 1 each key is
+// added to the list unless already there, with 0 each key is removed; the rest of the list is left as is
+fn process_set_exemptions_batch(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let (exempt, key_data) = rest_of_data.split_first()
+        .ok_or(CustomError::EmptyInstructionData)?;
+    let exempt = match exempt {
+        0 => false,
+        1 => true,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+    let chunks = key_data.chunks_exact(32);
+    if !chunks.remainder().is_empty() || chunks.len() == 0 || chunks.len() > MAX_FEE_EXEMPT {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    require_admin(admin, &token_config.config_admin(), "the config admin can change fee exemptions")?;
+    check_no_timelock(&token_config)?;
+
+    let old_exempt = token_config.fee_exempt;
+    for chunk in chunks {
+        let key = decode_pubkey(chunk)?;
+        if key == Pubkey::default() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let slot = token_config.fee_exempt.iter().position(|existing| *existing == key);
+        match (exempt, slot) {
+            (true, None) => {
+                let free = token_config.fee_exempt.iter()
+                    .position(|existing| *existing == Pubkey::default())
+                    .ok_or_else(|| {
+                        msg!("Fee exemption list is full at {} accounts", MAX_FEE_EXEMPT);
+                        ProgramError::InvalidArgument
+                    })?;
+                token_config.fee_exempt[free] = key;
+            },
+            (false, Some(slot)) => token_config.fee_exempt[slot] = Pubkey::default(),
+            _ => {},
+        }
+    }
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::FeeExemptChanged { signer: *admin.key, old_exempt, new_exempt: token_config.fee_exempt }.emit()?;
+
+    msg!("Transfer fee exemptions updated");
+    Ok(())
+}
+
+fn process_get_config(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+
+    set_return_data(&token_config.try_to_vec()?);
+    Ok(())
+}
+
+// Read-only: returns current supply, max supply and what's still mintable, as three u64 LE values
+fn process_get_supply_headroom(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_config_mint(&token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+
+    let remaining_mintable = token_config.max_supply.saturating_sub(mint_state.supply);
+
+    let mut payload = Vec::with_capacity(24);
+    payload.extend_from_slice(&mint_state.supply.to_le_bytes());
+    payload.extend_from_slice(&token_config.max_supply.to_le_bytes());
+    payload.extend_from_slice(&remaining_mintable.to_le_bytes());
+
+    set_return_data(&payload);
+    Ok(())
+}
+
+// Accounts are config, payer, supply history and system program. Anyone may create the history, and once it
+// exists every handler that changes max_supply takes it and records the change
+fn process_initialize_supply_history(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let history_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(payer)?;
+    let token_config = load_config(config_account, program_id)?;
+    if !token_config.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    let (history_address, history_bump) =
+        Pubkey::find_program_address(&[SUPPLY_HISTORY_SEED, config_account.key.as_ref()], program_id);
+    if *history_account.key != history_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !history_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let serialized = SupplyHistory::default().try_to_vec()?;
+    create_pda_account(
+        payer,
+        history_account,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[SUPPLY_HISTORY_SEED, config_account.key.as_ref(), &[history_bump]],
+    )?;
+
+    msg!("Supply history for {} created at {}", config_account.key, history_account.key);
+    Ok(())
+}
+
+fn load_supply_history(
+    history_account: &AccountInfo,
+    config_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<SupplyHistory, ProgramError> {
+    if history_account.owner != program_id {
+        msg!("Supply history {} is not owned by this program", history_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (history_address, _) =
+        Pubkey::find_program_address(&[SUPPLY_HISTORY_SEED, config_account.key.as_ref()], program_id);
+    if *history_account.key != history_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    SupplyHistory::try_from_slice(&history_account.try_borrow_data()?).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// Appends a cap change to the config's supply history, alongside the SupplyCapChanged event
+fn record_supply_change(
+    history_account: &AccountInfo,
+    config_account: &AccountInfo,
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    old_max_supply: u64,
+    new_max_supply: u64,
+) -> ProgramResult {
+    let mut history = load_supply_history(history_account, config_account, program_id)?;
+    history.push(SupplyChange { old_max_supply, new_max_supply, timestamp: Clock::get()?.unix_timestamp, signer: *signer });
+    history.serialize(&mut &mut history_account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+// Read-only: returns the recorded cap changes, oldest first, as a borsh Vec<SupplyChange>
+fn process_get_supply_history(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let history_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    load_config(config_account, program_id)?;
+    let history = load_supply_history(history_account, config_account, program_id)?;
+
+    set_return_data(&history.recent().try_to_vec()?);
+    Ok(())
+}
+
+// Read-only monitoring summary, one byte (0 or 1) per flag: program owns the config, config is rent-exempt,
+// a config migration is blocking transfers, minting is finalized, the mint admin is the mint's authority.
+// A config this program doesn't own reports only that first flag, and 0 for the rest
+fn process_health_check(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut flags = [0u8; 5];
+    if config_account.owner == program_id {
+        flags[0] = 1;
+        let token_config = load_config(config_account, program_id)?;
+        check_config_mint(&token_config, mint_account)?;
+        let mint_state = unpack_mint(mint_account)?;
+
+        flags[1] = Rent::get()?.is_exempt(config_account.lamports(), config_account.data_len()) as u8;
+        flags[2] = token_config.migration_in_progress as u8;
+        flags[3] = mint_state.mint_authority.is_none() as u8;
+        flags[4] = (mint_state.mint_authority == COption::Some(token_config.mint_admin())) as u8;
+    }
+
+    set_return_data(&flags);
+    Ok(())
+}
+
+// Read-only: returns CONFIG_LEN as a u64 LE so clients size and fund the config account from the deployed layout
+fn process_get_config_size() -> ProgramResult {
+    set_return_data(&(CONFIG_LEN as u64).to_le_bytes());
+    Ok(())
+}
+
+// Read-only deployment check: fails unless the config is ours and initialized, then returns admin(32) and initialized(1)
+fn process_verify_config(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    // load_config already rejects an account this program doesn't own
+    let token_config = load_config(config_account, program_id)?;
+    if !token_config.initialized {
+        msg!("Config account {} is not initialized", config_account.key);
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    let mut payload = Vec::with_capacity(33);
+    payload.extend_from_slice(token_config.admin_pubkey.as_ref());
+    payload.push(token_config.initialized as u8);
+
+    set_return_data(&payload);
+    Ok(())
+}
+
+// Logs an "advance_epoch" event with the new epoch and its start time, both LE
+fn process_advance_epoch(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.config_admin(), "the config admin can advance the epoch")?;
+
+    token_config.current_epoch = math::checked_add(token_config.current_epoch, 1)?;
+    token_config.epoch_started_ts = Clock::get()?.unix_timestamp;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    sol_log_data(&[
+        b"advance_epoch",
+        &token_config.current_epoch.to_le_bytes(),
+        &token_config.epoch_started_ts.to_le_bytes(),
+    ]);
+    Ok(())
+}
+
+fn saturating_i128(value: u128) -> i128 {
+    i128::try_from(value).unwrap_or(i128::MAX)
+}
+
+// Read-only: returns the mint's supply, the supply the config's counters account for, and the discrepancy
+// between them (supply - tracked, i64 LE), as three 8-byte LE values. Anything minted or burned around this
+// program, like a mint authority used directly, shows up as a discrepancy. The u128 counters can outgrow
+// those fields, so tracked and discrepancy saturate at their bounds instead
+fn process_audit_supply(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_config_mint(&token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+
+    // Burning tokens that were minted around the program can take the counters below zero, so work in i128
+    let tracked = saturating_i128(token_config.total_minted).saturating_sub(saturating_i128(token_config.total_burned));
+    let discrepancy = (mint_state.supply as i128).saturating_sub(tracked);
+    if discrepancy != 0 {
+        msg!(
+            "Warning: supply of {} is {} but the config accounts for {}",
+            mint_account.key,
+            mint_state.supply,
+            tracked
+        );
+    }
+
+    let mut payload = Vec::with_capacity(24);
+    payload.extend_from_slice(&mint_state.supply.to_le_bytes());
+    payload.extend_from_slice(&(tracked.clamp(0, u64::MAX as i128) as u64).to_le_bytes());
+    payload.extend_from_slice(&(discrepancy.clamp(i64::MIN as i128, i64::MAX as i128) as i64).to_le_bytes());
+
+    set_return_data(&payload);
+    Ok(())
+}
+
+// Payload is supply (u64 LE), slot (u64 LE) then the program id, so verifiers can hash it as-is
+fn process_attest_supply(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mint_state = unpack_mint(mint_account)?;
+    let slot = Clock::get()?.slot;
+
+    let mut payload = Vec::with_capacity(48);
+    payload.extend_from_slice(&mint_state.supply.to_le_bytes());
+    payload.extend_from_slice(&slot.to_le_bytes());
+    payload.extend_from_slice(program_id.as_ref());
+
+    msg!("Supply of {} at slot {} is {}", mint_account.key, slot, mint_state.supply);
+    set_return_data(&payload);
+    Ok(())
+}
+
+fn process_initialize_pool(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let fee_bps = decode_bps(instruction_data)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let mint_a = next_account_info(account_info_iter)?;
+    let mint_b = next_account_info(account_info_iter)?;
+    let vault_a = next_account_info(account_info_iter)?;
+    let vault_b = next_account_info(account_info_iter)?;
+    let lp_mint = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(payer)?;
+
+    if mint_a.key == mint_b.key {
+        msg!("A pool needs two different mints");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (pool_address, pool_bump) = Pubkey::find_program_address(
+        &[POOL_SEED, mint_a.key.as_ref(), mint_b.key.as_ref()],
+        program_id,
+    );
+    if *pool_account.key != pool_address {
+        msg!("Pool account {} is not the expected PDA {}", pool_account.key, pool_address);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Only an allocated pool counts as existing, a PDA that merely holds lamports is still created below.
+    // One that exists but was never initialized is written over, anything already initialized is left alone
+    let pool_exists = !pool_account.data_is_empty();
+    if pool_exists {
+        if pool_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let existing = PoolConfig::deserialize(&mut &pool_account.try_borrow_data()?[..])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if existing.initialized {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+    }
+
+    for (vault, mint) in [(vault_a, mint_a), (vault_b, mint_b)] {
+        if *vault.owner != spl_token::id() {
+            msg!("Vault {} is not owned by the token program", vault.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let vault_state = Account::unpack(&vault.try_borrow_data()?)?;
+        if vault_state.mint != *mint.key {
+            msg!("Vault {} does not hold mint {}", vault.key, mint.key);
+            return Err(CustomError::MintMismatch.into());
+        }
+        if vault_state.amount < MIN_POOL_LIQUIDITY {
+            msg!("Vault {} holds {}, a pool needs at least {} of each token", vault.key, vault_state.amount, MIN_POOL_LIQUIDITY);
+            return Err(CustomError::InsufficientLiquidity.into());
+        }
+    }
+    // LP tokens may only ever be minted by the pool PDA, otherwise whoever holds the LP mint authority
+    // could issue claims on the vaults
+    let lp_mint_state = unpack_mint(lp_mint)?;
+    if lp_mint_state.mint_authority != COption::Some(pool_address) {
+        msg!("LP mint {} must have the pool PDA {} as its mint authority", lp_mint.key, pool_address);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool_config = PoolConfig {
+        mint_a: *mint_a.key,
+        mint_b: *mint_b.key,
+        vault_a: *vault_a.key,
+        vault_b: *vault_b.key,
+        lp_mint: *lp_mint.key,
+        fee_bps,
+        initialized: true,
+    };
+    let serialized = pool_config.try_to_vec()?;
+
+    if !pool_exists {
+        create_pda_account(
+            payer,
+            pool_account,
+            system_program,
+            program_id,
+            serialized.len(),
+            &[POOL_SEED, mint_a.key.as_ref(), mint_b.key.as_ref(), &[pool_bump]],
+        )?;
+    }
+    pool_account.try_borrow_mut_data()?[..serialized.len()].copy_from_slice(&serialized);
+
+    msg!("Pool initialized for {} and {} with a {} bps fee", mint_a.key, mint_b.key, fee_bps);
+    Ok(())
+}
+
+// Read-only: returns reserve_a, reserve_b and the LP supply as three u64 LE values
+fn process_get_reserves(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let vault_a = next_account_info(account_info_iter)?;
+    let vault_b = next_account_info(account_info_iter)?;
+    let lp_mint = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool_config = PoolConfig::deserialize(&mut &pool_account.try_borrow_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    if !pool_config.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if *vault_a.key != pool_config.vault_a || *vault_b.key != pool_config.vault_b || *lp_mint.key != pool_config.lp_mint {
+        msg!("Vaults or LP mint do not match the pool config");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let reserve_a = Account::unpack(&vault_a.try_borrow_data()?)?.amount;
+    let reserve_b = Account::unpack(&vault_b.try_borrow_data()?)?.amount;
+    let lp_supply = unpack_mint(lp_mint)?.supply;
+
+    let mut payload = Vec::with_capacity(24);
+    payload.extend_from_slice(&reserve_a.to_le_bytes());
+    payload.extend_from_slice(&reserve_b.to_le_bytes());
+    payload.extend_from_slice(&lp_supply.to_le_bytes());
+
+    set_return_data(&payload);
+    Ok(())
+}
+
+// Read-only: quotes amount_in against the live vault balances and returns the output as u64 LE
+fn process_quote_swap(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    check_data_len(instruction_data, 10)?;
+    let amount_in = decode_amount(&instruction_data[..8])?;
+    let fee_bps = decode_bps(&instruction_data[8..10])?;
+
+    let account_info_iter = &mut accounts.iter();
+    let vault_in = next_account_info(account_info_iter)?;
+    let vault_out = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    if vault_in.key == vault_out.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let reserve_in = Account::unpack(&vault_in.try_borrow_data()?)?.amount;
+    let reserve_out = Account::unpack(&vault_out.try_borrow_data()?)?.amount;
+
+    let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee_bps)?;
+
+    msg!("Quote: {} in for {} out", amount_in, amount_out);
+    set_return_data(&amount_out.to_le_bytes());
+    Ok(())
+}
+
+fn process_transfer(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    transfer_tokens(accounts, accounts, instruction_data, None, program_id)
+}
+
+// Data is amount(8) + decimals(1). The mint comes first, then the usual transfer accounts, and the
+// transfer goes through the token program's transfer_checked
+fn process_transfer_checked(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(instruction_data, 9)?;
+    let (amount_data, decimals) = instruction_data.split_at(8);
+
+    let (mint_account, transfer_accounts) = accounts.split_first()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    transfer_tokens(transfer_accounts, accounts, amount_data, Some((mint_account, decimals[0])), program_id)
+}
+
+// Shared by both transfer paths: `accounts` are the transfer accounts, `cpi_accounts` everything the
+// instruction was given, and `checked` the mint and decimals when the caller went through TransferChecked.
+// An amount of u64::MAX is a sentinel for the source's entire balance, read on-chain at execution time
+fn transfer_tokens(
+    accounts: &[AccountInfo],
+    cpi_accounts: &[AccountInfo],
+    instruction_data: &[u8],
+    checked: Option<(&AccountInfo, u8)>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let mut amount = decode_amount(instruction_data)?;
+
+    if amount == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let from_account = next_account_info(account_info_iter)?;
+    let to_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    check_signer(authority)?;
+
+    let from_state = Account::unpack(&from_account.try_borrow_data()?)?;
+    let to_state = Account::unpack(&to_account.try_borrow_data()?)?;
+    if from_state.mint != to_state.mint {
+        msg!("Cannot transfer from a {} account to a {} account", from_state.mint, to_state.mint);
+        return Err(CustomError::MintMismatch.into());
+    }
+    if amount == u64::MAX {
+        amount = from_state.amount;
+        if amount == 0 {
+            msg!("{} has no balance to transfer", from_account.key);
+            return Err(CustomError::InvalidAmount.into());
+        }
+    }
+
+    let token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    // Both sides share a mint, so checking the source covers the destination too
+    check_account_mint(&token_config, from_account, &from_state)?;
+
+    match checked {
+        Some((mint_account, decimals)) => {
+            check_config_mint(&token_config, mint_account)?;
+            check_config_decimals(&token_config, &unpack_mint(mint_account)?)?;
+            if decimals != token_config.decimals {
+                msg!("Transfer is for {} decimals, the mint has {}", decimals, token_config.decimals);
+                return Err(CustomError::DecimalsMismatch.into());
+            }
+        },
+        None if token_config.require_checked_transfers => {
+            msg!("This token only accepts transfers through TransferChecked");
+            return Err(CustomError::InvalidAmount.into());
+        },
+        None => {},
+    }
+
+    if token_config.max_transfer_amount != 0 && amount > token_config.max_transfer_amount {
+        msg!("Transfer of {} exceeds the limit of {}", amount, token_config.max_transfer_amount);
+        return Err(CustomError::TransferLimitExceeded.into());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if now < token_config.transfers_unlocked_ts {
+        msg!("Transfers are locked until {}", token_config.transfers_unlocked_ts);
+        return Err(CustomError::TransfersLocked.into());
+    }
+
+    // The fee comes out of the amount, so the recipient gets the net and the sender still moves exactly `amount`
+    let fee_exempt = token_config.fee_exempt.contains(from_account.key)
+        || token_config.fee_exempt.contains(to_account.key);
+    let fee_amount = if fee_exempt { 0 } else { bps_of(amount, token_config.transfer_fee_bps)? };
+    let fee_treasury = if fee_amount > 0 {
+        let fee_treasury = next_account_info(account_info_iter)?;
+        if *fee_treasury.key != token_config.fee_treasury {
+            msg!("Fee treasury {} does not match the configured treasury", fee_treasury.key);
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Some(fee_treasury)
+    } else {
+        None
+    };
+    // Compounding needs the vault authority that holds the treasury and the yield vault on every charged transfer,
+    // since whether this one crosses the threshold is only known once its fee has landed
+    let compounding = match fee_treasury {
+        Some(fee_treasury) if token_config.compound_threshold > 0 => {
+            let vault_authority = next_account_info(account_info_iter)?;
+            let yield_vault = next_account_info(account_info_iter)?;
+            check_token_program(token_program)?;
+            let authority_bump = check_vault_authority(vault_authority, config_account.key, program_id)?;
+            check_vault(fee_treasury, vault_authority.key)?;
+            if *yield_vault.key != token_config.yield_vault {
+                msg!("Yield vault {} does not match the configured vault", yield_vault.key);
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Some((vault_authority, yield_vault, authority_bump))
+        },
+        _ => None,
+    };
+    // The SOL fee brings its own payer, separate from the token authority, plus the treasury and system program
+    let sol_fee = if token_config.sol_fee_lamports > 0 {
+        let payer = next_account_info(account_info_iter)?;
+        let sol_fee_treasury = next_account_info(account_info_iter)?;
+        let _system_program = next_account_info(account_info_iter)?;
+        check_signer(payer)?;
+        if *sol_fee_treasury.key != token_config.sol_fee_treasury {
+            msg!("SOL fee treasury {} does not match the configured treasury", sol_fee_treasury.key);
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Some((payer, sol_fee_treasury))
+    } else {
+        None
+    };
+    if token_config.require_opt_in {
+        let opt_in_account = next_account_info(account_info_iter)?;
+        check_recipient_opted_in(opt_in_account, config_account, &to_state.owner, program_id)?;
+    }
+    if token_config.whitelist_mode {
+        let sender_entry = next_account_info(account_info_iter)?;
+        let recipient_entry = next_account_info(account_info_iter)?;
+        check_whitelisted(sender_entry, config_account, &from_state.owner, program_id)?;
+        check_whitelisted(recipient_entry, config_account, &to_state.owner, program_id)?;
+    }
+    check_no_extra_accounts(account_info_iter)?;
+    let net_amount = amount - fee_amount;
+
+    if let Some((payer, sol_fee_treasury)) = sol_fee {
+        log_info!("SOL fee of {} lamports paid by {}", token_config.sol_fee_lamports, payer.key);
+        invoke(
+            &system_instruction::transfer(payer.key, sol_fee_treasury.key, token_config.sol_fee_lamports),
+            cpi_accounts,
+        )?;
+    }
+
+    let transfer_ix = |destination: &Pubkey, amount: u64| match checked {
+        Some((mint_account, decimals)) => transfer_checked(
+            token_program.key,
+            from_account.key,
+            mint_account.key,
+            destination,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        ),
+        None => transfer(
+            token_program.key,
+            from_account.key,
+            destination,
+            authority.key,
+            &[],
+            amount,
+        ),
+    };
+
+    log_info!("Transferring {} tokens from {} to {}", net_amount, from_account.key, to_account.key);
+
+    // The runtime only needs the CPI's accounts to be somewhere in the slice, so pass ours through instead of cloning each one
+    invoke(&transfer_ix(to_account.key, net_amount)?, cpi_accounts)?;
+
+    if let Some(fee_treasury) = fee_treasury {
+        log_info!("Transfer fee of {} tokens sent to {}", fee_amount, fee_treasury.key);
+        invoke(&transfer_ix(fee_treasury.key, fee_amount)?, cpi_accounts)?;
+
+        if let Some((vault_authority, yield_vault, authority_bump)) = compounding {
+            let collected = Account::unpack(&fee_treasury.try_borrow_data()?)?.amount;
+            if collected >= token_config.compound_threshold {
+                log_info!("Compounding {} fee tokens into {}", collected, yield_vault.key);
+                let compound_ix = transfer(
+                    token_program.key,
+                    fee_treasury.key,
+                    yield_vault.key,
+                    vault_authority.key,
+                    &[],
+                    collected,
+                )?;
+                invoke_signed(
+                    &compound_ix,
+                    cpi_accounts,
+                    &[&[VAULT_AUTHORITY_SEED, config_account.key.as_ref(), &[authority_bump]]],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Moves the source's whole balance as read on-chain, then goes through the same checks as a normal transfer
+fn process_transfer_all(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let from_account = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let amount = Account::unpack(&from_account.try_borrow_data()?)?.amount;
+    if amount == 0 {
+        msg!("{} has no balance to transfer", from_account.key);
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    process_transfer(accounts, &amount.to_le_bytes(), program_id)
+}
+
+// Data is amount(8) followed by a UTF-8 memo; the memo program comes first, then the usual transfer accounts
+fn process_transfer_with_memo(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(instruction_data)?;
+    if instruction_data.len() < 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (amount_data, memo) = instruction_data.split_at(8);
+    if memo.is_empty() || memo.len() > MAX_MEMO_LEN {
+        msg!("Memo must be between 1 and {} bytes", MAX_MEMO_LEN);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    std::str::from_utf8(memo).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    let (memo_program, transfer_accounts) = accounts.split_first()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if *memo_program.key != MEMO_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let memo_ix = Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![],
+        data: memo.to_vec(),
+    };
+    invoke(&memo_ix, accounts)?;
+
+    process_transfer(transfer_accounts, amount_data, program_id)
+}
+
+fn check_recipient_opted_in(
+    opt_in_account: &AccountInfo,
+    config_account: &AccountInfo,
+    wallet: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (opt_in_address, _) = Pubkey::find_program_address(
+        &[OPT_IN_SEED, config_account.key.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if *opt_in_account.key != opt_in_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // A wallet that never opted in has no record at all
+    let allowed = opt_in_account.owner == program_id
+        && OptInRecord::try_from_slice(&opt_in_account.try_borrow_data()?)
+            .map(|record| record.opted_in || record.exempt)
+            .unwrap_or(false);
+    if !allowed {
+        msg!("Recipient {} has not opted in", wallet);
+        return Err(CustomError::RecipientNotOptedIn.into());
+    }
+    Ok(())
+}
+
+fn check_whitelisted(
+    entry_account: &AccountInfo,
+    config_account: &AccountInfo,
+    wallet: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (entry_address, _) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, config_account.key.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if *entry_account.key != entry_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Wallets the admin never listed have no entry at all
+    let whitelisted = entry_account.owner == program_id
+        && WhitelistEntry::try_from_slice(&entry_account.try_borrow_data()?)
+            .map(|entry| entry.whitelisted)
+            .unwrap_or(false);
+    if !whitelisted {
+        msg!("{} is not whitelisted", wallet);
+        return Err(CustomError::AddressNotWhitelisted.into());
+    }
+    Ok(())
+}
+
+// Creates the opt-in record on first use; the returned record is what's currently stored
+fn load_or_create_opt_in<'a>(
+    opt_in_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    config_account: &AccountInfo,
+    wallet: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<OptInRecord, ProgramError> {
+    let (opt_in_address, opt_in_bump) = Pubkey::find_program_address(
+        &[OPT_IN_SEED, config_account.key.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if *opt_in_account.key != opt_in_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if opt_in_account.data_is_empty() {
+        create_pda_account(
+            payer,
+            opt_in_account,
+            system_program,
+            program_id,
+            OptInRecord::default().try_to_vec()?.len(),
+            &[OPT_IN_SEED, config_account.key.as_ref(), wallet.as_ref(), &[opt_in_bump]],
+        )?;
+        return Ok(OptInRecord::default());
+    }
+    if opt_in_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    OptInRecord::try_from_slice(&opt_in_account.try_borrow_data()?).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// The wallet signs for itself and pays for its record
+fn process_opt_in(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let wallet = next_account_info(account_info_iter)?;
+    let opt_in_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(wallet)?;
+    load_config(config_account, program_id)?;
+
+    let mut record = load_or_create_opt_in(opt_in_account, wallet, system_program, config_account, wallet.key, program_id)?;
+    record.opted_in = true;
+    record.serialize(&mut &mut opt_in_account.try_borrow_mut_data()?[..])?;
+
+    msg!("{} opted in to receive transfers", wallet.key);
+    Ok(())
+}
+
+// Data is a single 0/1 byte; the admin pays for the record if the wallet has none yet
+fn process_set_opt_in_exempt(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(rest_of_data)?;
+    let exempt = match rest_of_data {
+        [0] => false,
+        [1] => true,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let wallet = next_account_info(account_info_iter)?;
+    let opt_in_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.config_admin(), "the config admin can exempt recipients")?;
+
+    let mut record = load_or_create_opt_in(opt_in_account, admin, system_program, config_account, wallet.key, program_id)?;
+    record.exempt = exempt;
+    record.serialize(&mut &mut opt_in_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::OptInExemptChanged { signer: *admin.key, wallet: *wallet.key, exempt }.emit()?;
+
+    msg!("Opt-in exemption for {} set to {}", wallet.key, exempt);
+    Ok(())
+}
+
+// Creates the wSOL account if it doesn't exist yet, then moves lamports in and syncs the token balance
+fn process_wrap_sol(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let lamports = decode_amount(instruction_data)?;
+
+    if lamports == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+    let wsol_account = next_account_info(account_info_iter)?;
+    let native_mint = next_account_info(account_info_iter)?;
+    let owner = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(payer)?;
+
+    if *system_program.key != solana_program::system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *native_mint.key != spl_token::native_mint::id() {
+        msg!("{} is not the native mint", native_mint.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if wsol_account.data_is_empty() {
+        check_signer(wsol_account)?;
+
+        let rent = Rent::get()?;
+        let create_ix = system_instruction::create_account(
+            payer.key,
+            wsol_account.key,
+            rent.minimum_balance(Account::LEN),
+            Account::LEN as u64,
+            token_program.key,
+        );
+        invoke(&create_ix, accounts)?;
+
+        let init_ix = initialize_account3(
+            token_program.key,
+            wsol_account.key,
+            native_mint.key,
+            owner.key,
+        )?;
+        invoke(&init_ix, accounts)?;
+    }
+
+    msg!("Wrapping {} lamports into {}", lamports, wsol_account.key);
+    let transfer_ix = system_instruction::transfer(payer.key, wsol_account.key, lamports);
+    invoke(&transfer_ix, accounts)?;
+
+    let sync_ix = sync_native(token_program.key, wsol_account.key)?;
+    invoke(&sync_ix, accounts)?;
+
+    check_rent_exempt(payer)?;
+    check_rent_exempt(wsol_account)
+}
+
+// Closing a wSOL account hands its whole lamport balance, wrapped amount included, to the destination
+fn process_unwrap_sol(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let wsol_account = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let owner = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(owner)?;
+
+    let wsol_state = Account::unpack(&wsol_account.try_borrow_data()?)?;
+    if !wsol_state.is_native() {
+        msg!("{} is not a wrapped SOL account", wsol_account.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    msg!("Unwrapping {} into {}", wsol_account.key, destination.key);
+    let close_ix = close_account(
+        token_program.key,
+        wsol_account.key,
+        destination.key,
+        owner.key,
+        &[],
+    )?;
+
+    invoke(&close_ix, accounts)?;
+    Ok(())
+}
+
+// Data is a single 0/1 byte; the admin pays for the entry the first time a wallet is listed
+fn process_set_whitelisted(accounts: &[AccountInfo], rest_of_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(rest_of_data)?;
+    let whitelisted = match rest_of_data {
+        [0] => false,
+        [1] => true,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let wallet = next_account_info(account_info_iter)?;
+    let entry_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.config_admin(), "the config admin can manage the whitelist")?;
+
+    let (entry_address, entry_bump) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, config_account.key.as_ref(), wallet.key.as_ref()],
+        program_id,
+    );
+    if *entry_account.key != entry_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if entry_account.data_is_empty() {
+        create_pda_account(
+            admin,
+            entry_account,
+            system_program,
+            program_id,
+            WhitelistEntry::default().try_to_vec()?.len(),
+            &[WHITELIST_SEED, config_account.key.as_ref(), wallet.key.as_ref(), &[entry_bump]],
+        )?;
+    } else if entry_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    WhitelistEntry { whitelisted }.serialize(&mut &mut entry_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::WhitelistChanged { signer: *admin.key, wallet: *wallet.key, whitelisted }.emit()?;
+
+    msg!("Whitelist entry for {} set to {}", wallet.key, whitelisted);
+    Ok(())
+}
+
+// Reclaims the rent of a zero-balance token account, paying it back to the account's owner
+fn process_close_empty_account(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_account = next_account_info(account_info_iter)?;
+    let owner = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(owner)?;
+
+    let token_state = Account::unpack(&token_account.try_borrow_data()?)?;
+    if token_state.owner != *owner.key {
+        msg!("{} is not the owner of {}", owner.key, token_account.key);
+        return Err(CustomError::Unauthorized.into());
+    }
+    if token_state.amount != 0 {
+        msg!("{} still holds {} tokens", token_account.key, token_state.amount);
+        return Err(CustomError::AccountNotEmpty.into());
+    }
+
+    msg!("Closing empty account {}", token_account.key);
+    let close_ix = close_account(
+        token_program.key,
+        token_account.key,
+        owner.key,
+        owner.key,
+        &[],
+    )?;
+
+    invoke(&close_ix, accounts)?;
+    Ok(())
+}
+
+fn process_approve(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let amount = decode_amount(instruction_data)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let delegate = next_account_info(account_info_iter)?;
+    let owner = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(owner)?;
+
+    // The owner can already spend its own tokens, so this is almost certainly a client passing the wrong key
+    if delegate.key == owner.key {
+        msg!("Delegate {} is the account owner", delegate.key);
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    msg!("Approving {} to spend {} tokens from {}", delegate.key, amount, source_account.key);
+    let approve_ix = approve(
+        token_program.key,
+        source_account.key,
+        delegate.key,
+        owner.key,
+        &[],
+        amount,
+    )?;
+
+    invoke(&approve_ix, accounts)?;
+    Ok(())
+}
+
+fn process_revoke(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let owner = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(owner)?;
+
+    msg!("Revoking delegate on {}", source_account.key);
+    let revoke_ix = revoke(
+        token_program.key,
+        source_account.key,
+        owner.key,
+        &[],
+    )?;
+
+    invoke(&revoke_ix, accounts)?;
+    Ok(())
+}
+
+// Accounts come in (source, owner) pairs after the token program, since revoke needs each owner's signature
+fn process_batch_revoke(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_program = next_account_info(account_info_iter)?;
+    let pairs = account_info_iter.as_slice().chunks_exact(2);
+
+    if pairs.len() == 0 || !pairs.remainder().is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if pairs.len() > MAX_BATCH_REVOKE {
+        msg!("Batch revoke is limited to {} accounts", MAX_BATCH_REVOKE);
+        return Err(CustomError::TooManyAccounts.into());
+    }
+
+    for pair in pairs.clone() {
+        check_signer(&pair[1])?;
+    }
+
+    for pair in pairs {
+        let (source_account, owner) = (&pair[0], &pair[1]);
+
+        msg!("Revoking delegate on {}", source_account.key);
+        let revoke_ix = revoke(
+            token_program.key,
+            source_account.key,
+            owner.key,
+            &[],
+        )?;
+
+        invoke(&revoke_ix, accounts)?;
+    }
+    Ok(())
+}
+
+fn process_freeze(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let freeze_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_freeze_authority(mint_account, freeze_authority)?;
+
+    msg!("Freezing {}", token_account.key);
+    let freeze_ix = freeze_account(
+        token_program.key,
+        token_account.key,
+        mint_account.key,
+        freeze_authority.key,
+        &[],
+    )?;
+
+    invoke(&freeze_ix, accounts)?;
+    Ok(())
+}
+
+fn process_thaw(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let freeze_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_freeze_authority(mint_account, freeze_authority)?;
+
+    msg!("Thawing {}", token_account.key);
+    let thaw_ix = thaw_account(
+        token_program.key,
+        token_account.key,
+        mint_account.key,
+        freeze_authority.key,
+        &[],
+    )?;
+
+    invoke(&thaw_ix, accounts)?;
+    Ok(())
+}
+
+// Every check a single mint must pass before its CPI, shared by Mint, BatchMint (on the batch total) and SimulateMint.
+// Counts the amount against the daily window, so callers that don't persist the config leave it untouched
+fn validate_mint(
+    token_config: &mut TokenConfig,
+    accounts: &[AccountInfo],
+    mint_account: &AccountInfo,
+    mint_authority: &AccountInfo,
+    amount: u64,
+) -> ProgramResult {
+    check_not_migrating(token_config)?;
+    if token_config.mint_permanently_disabled {
+        msg!("Minting has been permanently disabled");
+        return Err(CustomError::MintingFinalized.into());
+    }
+
+    // Now we check the authority for the signer that is minting
+    check_signer(mint_authority)?;
+
+    if *mint_authority.key != token_config.mint_admin() {
+        msg!("Unauthorized: Only the mint admin can mint tokens.");
+        return Err(CustomError::UnauthorizedMint.into());
+    }
+    check_admin_threshold(token_config, accounts)?;
+    check_high_value_cosigner(token_config, accounts, amount)?;
+
+    if amount < token_config.min_mint_amount {
+        msg!("Mint amount {} is below the minimum of {}", amount, token_config.min_mint_amount);
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    check_config_mint(token_config, mint_account)?;
+    let mint_state = unpack_mint(mint_account)?;
+    check_config_decimals(token_config, &mint_state)?;
+    check_mint_authority(&mint_state, mint_authority)?;
+    check_expected_freeze_authority(&mint_state, token_config)?;
+    let new_supply = math::checked_add(mint_state.supply, amount)?;
+
+    if token_config.max_supply != UNLIMITED_SUPPLY && token_config.post_cap_annual_inflation_bps > 0 {
+        check_hybrid_supply(token_config, mint_state.supply, amount)?;
+    } else if token_config.max_supply != UNLIMITED_SUPPLY && new_supply > token_config.max_supply {
+        msg!("Minting would exceed max supply limit of {}", token_config.max_supply);
+        return Err(CustomError::MaxSupplyExceeded.into());
+    }
+
+    if token_config.max_mint_operations != 0 && token_config.mint_count >= token_config.max_mint_operations {
+        msg!("Minting would exceed max mint operations limit of {}", token_config.max_mint_operations);
+        return Err(CustomError::MintOperationsExceeded.into());
+    }
+    record_daily_mint(token_config, amount)
+}
+
+// Read-only: runs the mint checks without minting and returns the outcome as a u64 LE status,
+// 0 when the mint would succeed and the ProgramError code (CustomError codes included) otherwise
+fn process_simulate_mint(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let amount = decode_amount(instruction_data)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let mint_authority = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    let status = load_config(config_account, program_id)
+        .and_then(|mut token_config| validate_mint(&mut token_config, accounts, mint_account, mint_authority, amount))
+        .map_or_else(u64::from, |_| 0);
+
+    set_return_data(&status.to_le_bytes());
+    Ok(())
+}
+
+// Same shape as load_or_create_opt_in; the mint admin pays for a destination's first receipt
+fn load_or_create_mint_receipt<'a>(
+    receipt_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    config_account: &AccountInfo,
+    destination: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<MintReceipt, ProgramError> {
+    let (receipt_address, receipt_bump) = Pubkey::find_program_address(
+        &[MINT_RECEIPT_SEED, config_account.key.as_ref(), destination.as_ref()],
+        program_id,
+    );
+    if *receipt_account.key != receipt_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if receipt_account.data_is_empty() {
+        create_pda_account(
+            payer,
+            receipt_account,
+            system_program,
+            program_id,
+            MintReceipt::default().try_to_vec()?.len(),
+            &[MINT_RECEIPT_SEED, config_account.key.as_ref(), destination.as_ref(), &[receipt_bump]],
+        )?;
+        return Ok(MintReceipt::default());
+    }
+    if receipt_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    MintReceipt::try_from_slice(&receipt_account.try_borrow_data()?).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// An existing destination gets the checks the token program would otherwise fail on without saying why
+fn check_mint_destination(destination_account: &AccountInfo, mint_account: &AccountInfo) -> ProgramResult {
+    let destination_state = Account::unpack(&destination_account.try_borrow_data()?)?;
+    if destination_state.mint != *mint_account.key {
+        msg!("Destination {} does not hold mint {}", destination_account.key, mint_account.key);
+        return Err(CustomError::MintMismatch.into());
+    }
+    if destination_state.state == AccountState::Frozen {
+        msg!("Destination {} is frozen", destination_account.key);
+        return Err(CustomError::AccountFrozen.into());
+    }
+    Ok(())
+}
+
+// Counts a mint against the destination's lifetime per_account_mint_cap, when there is one, and stores the receipt
+fn record_account_mint(
+    token_config: &TokenConfig,
+    receipt_account: &AccountInfo,
+    mut receipt: MintReceipt,
+    destination: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    let received = math::checked_add(receipt.received, amount)?;
+    if token_config.per_account_mint_cap > 0 && received > token_config.per_account_mint_cap {
+        msg!("{} has received {} of its {} mint cap", destination, receipt.received, token_config.per_account_mint_cap);
+        return Err(CustomError::PerAccountCapExceeded.into());
+    }
+    receipt.received = received;
+    receipt.serialize(&mut &mut receipt_account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+// Counts a destination that had no receipt yet against its owner's max_accounts_per_owner; the mint admin
+// pays for the owner's first record
+fn record_owner_account<'a>(
+    token_config: &TokenConfig,
+    owner_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    config_account: &AccountInfo,
+    destination_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let owner = Account::unpack(&destination_account.try_borrow_data()?)?.owner;
+    let (owner_address, owner_bump) =
+        Pubkey::find_program_address(&[OWNER_ACCOUNTS_SEED, config_account.key.as_ref(), owner.as_ref()], program_id);
+    if *owner_account.key != owner_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let mut record = if owner_account.data_is_empty() {
+        create_pda_account(
+            payer,
+            owner_account,
+            system_program,
+            program_id,
+            OwnerAccounts::default().try_to_vec()?.len(),
+            &[OWNER_ACCOUNTS_SEED, config_account.key.as_ref(), owner.as_ref(), &[owner_bump]],
+        )?;
+        OwnerAccounts::default()
+    } else {
+        if owner_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        OwnerAccounts::try_from_slice(&owner_account.try_borrow_data()?).map_err(|_| ProgramError::InvalidAccountData)?
+    };
+
+    if record.count >= token_config.max_accounts_per_owner {
+        msg!("{} already has {} of its {} token accounts", owner, record.count, token_config.max_accounts_per_owner);
+        return Err(CustomError::OwnerAccountLimitExceeded.into());
+    }
+    record.count += 1;
+    record.serialize(&mut &mut owner_account.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+fn process_mint(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let amount = decode_amount(instruction_data)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let mint_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+    validate_mint(&mut token_config, accounts, mint_account, mint_authority, amount)?;
+
+    // The rewards slice comes out of the requested amount, so the cap check above still covers the total
+    let rewards_amount = bps_of(amount, token_config.mint_rewards_bps)?;
+    let rewards_vault = if rewards_amount > 0 {
+        let rewards_vault = next_account_info(account_info_iter)?;
+        if *rewards_vault.key != token_config.rewards_vault {
+            msg!("Rewards vault {} does not match the configured vault", rewards_vault.key);
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let vault_state = Account::unpack(&rewards_vault.try_borrow_data()?)?;
+        if vault_state.mint != *mint_account.key {
+            msg!("Rewards vault {} does not hold mint {}", rewards_vault.key, mint_account.key);
+            return Err(CustomError::MintMismatch.into());
+        }
+        Some(rewards_vault)
+    } else {
+        None
+    };
+
+    token_config.mint_count = math::checked_add(token_config.mint_count, 1)?;
+    token_config.total_minted = token_config.total_minted.checked_add(amount as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    // A destination that doesn't exist yet is created as the wallet's ATA, using the trailing payer accounts
+    if destination_account.data_is_empty() {
+        let payer = next_account_info(account_info_iter)?;
+        let wallet = next_account_info(account_info_iter)?;
+        let _system_program = next_account_info(account_info_iter)?;
+        let associated_token_program = next_account_info(account_info_iter)?;
+
+        check_signer(payer)?;
+
+        if *associated_token_program.key != spl_associated_token_account::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let ata = get_associated_token_address_with_program_id(wallet.key, mint_account.key, token_program.key);
+        if ata != *destination_account.key {
+            msg!("Destination {} is not the associated token account {} of {}", destination_account.key, ata, wallet.key);
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        msg!("Creating associated token account {} for {}", destination_account.key, wallet.key);
+        let create_ix = create_associated_token_account(
+            payer.key,
+            wallet.key,
+            mint_account.key,
+            token_program.key,
+        );
+        invoke(&create_ix, accounts)?;
+    } else {
+        check_mint_destination(destination_account, mint_account)?;
+    }
+
+    if token_config.per_account_mint_cap > 0 || token_config.max_accounts_per_owner > 0 {
+        let receipt_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let new_destination = receipt_account.data_is_empty();
+        let receipt = load_or_create_mint_receipt(
+            receipt_account,
+            mint_authority,
+            system_program,
+            config_account,
+            destination_account.key,
+            program_id,
+        )?;
+        record_account_mint(&token_config, receipt_account, receipt, destination_account.key, amount - rewards_amount)?;
+        if token_config.max_accounts_per_owner > 0 && new_destination {
+            let owner_account = next_account_info(account_info_iter)?;
+            record_owner_account(
+                &token_config,
+                owner_account,
+                mint_authority,
+                system_program,
+                config_account,
+                destination_account,
+                program_id,
+            )?;
+        }
+    }
+
+    log_info!("Minting {} tokens to {}", amount - rewards_amount, destination_account.key);
+    let mint_ix = mint_to(
+        token_program.key,
+        mint_account.key,
+        destination_account.key,
+        mint_authority.key,
+        &[],
+        amount - rewards_amount,
+    )?;
+
+    invoke(&mint_ix, accounts)?;
+
+    if let Some(rewards_vault) = rewards_vault {
+        log_info!("Minting {} reward tokens to {}", rewards_amount, rewards_vault.key);
+        let rewards_ix = mint_to(
+            token_program.key,
+            mint_account.key,
+            rewards_vault.key,
+            mint_authority.key,
+            &[],
+            rewards_amount,
+        )?;
+
+        invoke(&rewards_ix, accounts)?;
+    }
+    Ok(())
+}
+
+// Accounts are mint, mint admin, token program, config and one destination per amount. With a per-account
+// mint cap or a per-owner account limit those are followed by one receipt per destination and the system
+// program, and with the limit by one owner record per destination; anything after that is only there to sign,
+// like the cosigner or admin signers
+fn process_batch_mint(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    // Amounts are read straight out of the instruction data, so memory stays flat however large the batch
+    let chunks = instruction_data.chunks_exact(8);
+    check_data_not_empty(instruction_data)?;
+    if !chunks.remainder().is_empty() {
+        return Err(CustomError::InvalidAmount.into());
+    }
+    let leg_count = chunks.len();
+
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let mint_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let remaining_accounts = account_info_iter.as_slice();
+
+    if remaining_accounts.len() < leg_count {
+        msg!("Expected {} destination accounts, got {}", leg_count, remaining_accounts.len());
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (destination_accounts, remaining_accounts) = remaining_accounts.split_at(leg_count);
+
+    let mut token_config = load_config(config_account, program_id)?;
+
+    let mut total: u64 = 0;
+    for chunk in instruction_data.chunks_exact(8) {
+        let amount = decode_amount(chunk)?;
+        if amount == 0 || amount < token_config.min_mint_amount {
+            return Err(CustomError::InvalidAmount.into());
+        }
+        total = math::checked_add(total, amount)?;
+    }
+
+    // The batch counts as one mint of its total, so it can't stop halfway and splitting a large mint into
+    // small legs doesn't get around the cosigner
+    validate_mint(&mut token_config, accounts, mint_account, mint_authority, total)?;
+    for destination_account in destination_accounts {
+        check_mint_destination(destination_account, mint_account)?;
+    }
+
+    if token_config.per_account_mint_cap > 0 || token_config.max_accounts_per_owner > 0 {
+        let owner_count = if token_config.max_accounts_per_owner > 0 { leg_count } else { 0 };
+        if remaining_accounts.len() <= leg_count + owner_count {
+            msg!("Expected {} receipt accounts, the system program and {} owner records", leg_count, owner_count);
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (receipt_accounts, remaining_accounts) = remaining_accounts.split_at(leg_count);
+        let system_program = &remaining_accounts[0];
+        let owner_accounts = &remaining_accounts[1..=owner_count];
+        for (leg, ((destination_account, receipt_account), chunk)) in destination_accounts
+            .iter()
+            .zip(receipt_accounts)
+            .zip(instruction_data.chunks_exact(8))
+            .enumerate()
+        {
+            let new_destination = receipt_account.data_is_empty();
+            let receipt = load_or_create_mint_receipt(
+                receipt_account,
+                mint_authority,
+                system_program,
+                config_account,
+                destination_account.key,
+                program_id,
+            )?;
+            record_account_mint(&token_config, receipt_account, receipt, destination_account.key, decode_amount(chunk)?)?;
+            if owner_count > 0 && new_destination {
+                record_owner_account(
+                    &token_config,
+                    &owner_accounts[leg],
+                    mint_authority,
+                    system_program,
+                    config_account,
+                    destination_account,
+                    program_id,
+                )?;
+            }
+        }
+    }
+
+    token_config.mint_count = math::checked_add(token_config.mint_count, 1)?;
+    token_config.total_minted = token_config.total_minted.checked_add(total as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+
+    log_info!("Minting {} tokens to {} accounts", total, destination_accounts.len());
+    for (destination_account, chunk) in destination_accounts.iter().zip(instruction_data.chunks_exact(8)) {
+        let amount = decode_amount(chunk)?;
+        let mint_ix = mint_to(
+            token_program.key,
+            mint_account.key,
+            destination_account.key,
+            mint_authority.key,
+            &[],
+            amount,
+        )?;
+
+        invoke(&mint_ix, accounts)?;
+    }
+    Ok(())
+}
+
+fn process_validate_batch(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let chunks = instruction_data.chunks_exact(8);
+    check_data_not_empty(instruction_data)?;
+    if !chunks.remainder().is_empty() {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+
+    let source_state = Account::unpack(&source_account.try_borrow_data()?)?;
+
+    // Count the transfers that fit in the balance, in order, stopping at the first one that doesn't
+    let mut remaining = source_state.amount;
+    let mut fits: u64 = 0;
+    for chunk in chunks {
+        let amount = decode_amount(chunk)?;
+        if amount == 0 {
+            return Err(CustomError::InvalidAmount.into());
+        }
+        match remaining.checked_sub(amount) {
+            Some(left) => {
+                remaining = left;
+                fits += 1;
+            },
+            None => break,
+        }
+    }
+
+    msg!("{} of {} transfers fit in the balance of {}", fits, instruction_data.len() / 8, source_account.key);
+    set_return_data(&fits.to_le_bytes());
+    Ok(())
+}
+
+// Airdrop claim: leaf is keccak(index, claimant, amount), paid out of a vault owned by the vault authority PDA
+fn process_claim(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_not_empty(instruction_data)?;
+    if instruction_data.len() < 16 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let index = u64::from_le_bytes(instruction_data[..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let amount = decode_amount(&instruction_data[8..16])?;
+    let proof = &instruction_data[16..];
+    if !proof.chunks_exact(32).remainder().is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let claim_bitmap = next_account_info(account_info_iter)?;
+    let claimant = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(claimant)?;
+    check_token_program(token_program)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    if !token_config.initialized || token_config.merkle_root == [0; 32] {
+        msg!("No airdrop is open");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if *vault.key != token_config.airdrop_vault {
+        msg!("Vault {} is not the airdrop vault {}", vault.key, token_config.airdrop_vault);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if index as usize >= CLAIM_BITMAP_LEN * 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let leaf = keccak::hashv(&[&index.to_le_bytes(), claimant.key.as_ref(), &amount.to_le_bytes()]).to_bytes();
+    if !verify_merkle_proof(leaf, proof, &token_config.merkle_root) {
+        msg!("Invalid merkle proof for claim {}", index);
+        return Err(CustomError::InvalidProof.into());
+    }
+
+    // Each root gets its own bitmap, so opening a new airdrop starts with nothing claimed
+    let (bitmap_address, bitmap_bump) = Pubkey::find_program_address(
+        &[CLAIMS_SEED, config_account.key.as_ref(), &token_config.merkle_root],
+        program_id,
+    );
+    if *claim_bitmap.key != bitmap_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if claim_bitmap.data_is_empty() {
+        create_pda_account(
+            claimant,
+            claim_bitmap,
+            system_program,
+            program_id,
+            CLAIM_BITMAP_LEN,
+            &[CLAIMS_SEED, config_account.key.as_ref(), &token_config.merkle_root, &[bitmap_bump]],
+        )?;
+    } else if claim_bitmap.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    {
+        let mut bitmap = claim_bitmap.try_borrow_mut_data()?;
+        let (byte, bit) = (index as usize / 8, 1u8 << (index % 8));
+        if bitmap[byte] & bit != 0 {
+            msg!("Claim {} was already made", index);
+            return Err(CustomError::AlreadyClaimed.into());
+        }
+        bitmap[byte] |= bit;
+    }
+
+    let authority_bump = check_vault_authority(vault_authority, config_account.key, program_id)?;
+
+    msg!("Claiming {} tokens for {}", amount, claimant.key);
+    let transfer_ix = transfer(
+        token_program.key,
+        vault.key,
+        destination_account.key,
+        vault_authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        accounts,
+        &[&[VAULT_AUTHORITY_SEED, config_account.key.as_ref(), &[authority_bump]]],
+    )?;
+    Ok(())
 }
 
-impl From<CustomError> for ProgramError {
-    fn from(e: CustomError) -> Self {
-        ProgramError::Custom(e as u32)
-    }
-}
+fn process_create_vesting(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(instruction_data, 32)?;
+    let start_ts = i64::from_le_bytes(instruction_data[..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let cliff_ts = i64::from_le_bytes(instruction_data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let end_ts = i64::from_le_bytes(instruction_data[16..24].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let total = decode_amount(&instruction_data[24..32])?;
 
-// This function here will deserialize the u64 amount from instruction data, for security reasons
-fn decode_amount(data: &[u8]) -> Result<u64, ProgramError> {
-    if data.len() != 8 {
+    if total == 0 {
         return Err(CustomError::InvalidAmount.into());
     }
-    Ok(u64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?))
-}
-
-// This function here will check the signer for security reason
-fn check_signer(account: &AccountInfo) -> ProgramResult {
-    if !account.is_signer {
-        msg!("Missing required signature for account: {}", account.key);
-        return Err(ProgramError::MissingRequiredSignature);
+    if start_ts > cliff_ts || cliff_ts > end_ts || start_ts >= end_ts {
+        msg!("Vesting schedule must satisfy start <= cliff <= end with start < end");
+        return Err(ProgramError::InvalidInstructionData);
     }
-    Ok(())
-}
 
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    let (instruction, rest_of_data) = instruction_data.split_first()
-        .ok_or(ProgramError::InvalidInstructionData)?;
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let vesting_account = next_account_info(account_info_iter)?;
+    let beneficiary = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
 
-    match instruction {
-        0 => process_transfer(accounts, rest_of_data),
-        1 => process_mint(accounts, rest_of_data, program_id),
-        2 => process_burn(accounts, rest_of_data),
-        3 => process_initialize(accounts, rest_of_data),
-        _ => Err(ProgramError::InvalidInstructionData),
+    let token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.mint_admin(), "the mint admin can create vesting schedules")?;
+    check_not_migrating(&token_config)?;
+
+    let (vesting_address, vesting_bump) = Pubkey::find_program_address(
+        &[VESTING_SEED, config_account.key.as_ref(), beneficiary.key.as_ref()],
+        program_id,
+    );
+    if *vesting_account.key != vesting_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !vesting_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
     }
-}
 
-fn process_initialize(accounts: &[AccountInfo], rest_of_data: &[u8]) -> ProgramResult {
-    if rest_of_data.len() < 40 {
-        return Err(ProgramError::InvalidInstructionData);
+    // Each schedule signs for its own vault, which must already hold everything it promises
+    let (authority_address, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, vesting_address.as_ref()], program_id);
+    let vault_state = check_vault(vault, &authority_address)?;
+    if vault_state.mint != token_config.mint {
+        msg!("Vault {} holds {} tokens, not {}", vault.key, vault_state.mint, token_config.mint);
+        return Err(CustomError::MintMismatch.into());
+    }
+    if vault_state.amount < total {
+        msg!("Vault {} holds {} tokens, short of the {} to vest", vault.key, vault_state.amount, total);
+        return Err(ProgramError::InsufficientFunds);
     }
 
-    let admin_pubkey = Pubkey::new_from_array(rest_of_data[..32].try_into().unwrap());
-    let max_supply = u64::from_le_bytes(rest_of_data[32..40].try_into().unwrap());
+    let schedule = VestingSchedule {
+        beneficiary: *beneficiary.key,
+        vault: *vault.key,
+        start_ts,
+        cliff_ts,
+        end_ts,
+        total,
+        released: 0,
+    };
+    let serialized = schedule.try_to_vec()?;
+
+    create_pda_account(
+        admin,
+        vesting_account,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[VESTING_SEED, config_account.key.as_ref(), beneficiary.key.as_ref(), &[vesting_bump]],
+    )?;
+    vesting_account.try_borrow_mut_data()?.copy_from_slice(&serialized);
+
+    AdminEvent::VestingCreated {
+        signer: *admin.key,
+        beneficiary: *beneficiary.key,
+        vault: *vault.key,
+        start_ts,
+        cliff_ts,
+        end_ts,
+        total,
+    }.emit()?;
+
+    msg!("Vesting {} tokens for {} from {} to {}", total, beneficiary.key, start_ts, end_ts);
+    Ok(())
+}
 
+fn process_release(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let config_account = next_account_info(account_info_iter)?;
+    let vesting_account = next_account_info(account_info_iter)?;
+    let beneficiary = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
 
-    // Ensure account size is enough for TokenConfig
-    if config_account.data_len() < TokenConfig::default().try_to_vec()?.len() {
+    check_signer(beneficiary)?;
+    check_token_program(token_program)?;
+
+    if vesting_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut vesting_data = vesting_account.try_borrow_mut_data()?;
+    let mut schedule = VestingSchedule::try_from_slice(&vesting_data).map_err(|_| ProgramError::InvalidAccountData)?;
+
+    let (vesting_address, _) = Pubkey::find_program_address(
+        &[VESTING_SEED, config_account.key.as_ref(), schedule.beneficiary.as_ref()],
+        program_id,
+    );
+    if *vesting_account.key != vesting_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *beneficiary.key != schedule.beneficiary {
+        msg!("Only the beneficiary {} can release this schedule", schedule.beneficiary);
+        return Err(CustomError::Unauthorized.into());
+    }
+    if *vault.key != schedule.vault {
+        msg!("Vault {} does not match the vesting schedule", vault.key);
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut config_data = config_account.try_borrow_mut_data()?;
-    let mut token_config = TokenConfig::try_from_slice(&config_data).map_err(|_| ProgramError::InvalidAccountData)?;
+    let destination_state = Account::unpack(&destination_account.try_borrow_data()?)?;
+    if destination_state.owner != schedule.beneficiary {
+        msg!("Destination {} is not owned by the beneficiary", destination_account.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
 
-    if token_config.initialized {
-        return Err(ProgramError::AccountAlreadyInitialized);
+    let now = Clock::get()?.unix_timestamp;
+    let releasable = schedule.vested_amount(now).saturating_sub(schedule.released);
+    if releasable == 0 {
+        msg!("Nothing to release yet");
+        return Ok(());
     }
 
-    token_config.admin_pubkey = admin_pubkey;
-    token_config.max_supply = max_supply;
-    token_config.initialized = true;
+    schedule.released += releasable;
+    schedule.serialize(&mut &mut vesting_data[..])?;
+    drop(vesting_data);
+
+    let authority_bump = check_vault_authority(vault_authority, vesting_account.key, program_id)?;
 
-    token_config.serialize(&mut *config_data)?;
+    msg!("Releasing {} vested tokens to {}", releasable, destination_account.key);
+    let transfer_ix = transfer(
+        token_program.key,
+        vault.key,
+        destination_account.key,
+        vault_authority.key,
+        &[],
+        releasable,
+    )?;
 
-    msg!("Token initialized with admin: {} and max supply: {}", admin_pubkey, max_supply);
+    invoke_signed(
+        &transfer_ix,
+        accounts,
+        &[&[VAULT_AUTHORITY_SEED, vesting_account.key.as_ref(), &[authority_bump]]],
+    )?;
     Ok(())
 }
 
-fn process_transfer(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
-    let amount = decode_amount(instruction_data)?;
-
+// Data is amount(8) + unlock_ts(8); the escrow is the PDA of [ESCROW_SEED, config, depositor, recipient]
+fn process_escrow_deposit(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(instruction_data, 16)?;
+    let amount = decode_amount(&instruction_data[..8])?;
+    let unlock_ts = decode_timestamp(&instruction_data[8..])?;
     if amount == 0 {
         return Err(CustomError::InvalidAmount.into());
     }
 
     let account_info_iter = &mut accounts.iter();
-    let from_account = next_account_info(account_info_iter)?;
-    let to_account = next_account_info(account_info_iter)?;
-    let authority = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let depositor = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let recipient = next_account_info(account_info_iter)?;
+    let source_account = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(depositor)?;
+    check_token_program(token_program)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+
+    let (escrow_address, escrow_bump) = Pubkey::find_program_address(
+        &[ESCROW_SEED, config_account.key.as_ref(), depositor.key.as_ref(), recipient.key.as_ref()],
+        program_id,
+    );
+    if *escrow_account.key != escrow_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !escrow_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    // Every escrow holds its tokens under its own authority, so releasing one can't reach another's deposit
+    let (authority_address, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, escrow_address.as_ref()], program_id);
+    let vault_state = check_vault(vault, &authority_address)?;
+    let source_state = Account::unpack(&source_account.try_borrow_data()?)?;
+    check_account_mint(&token_config, source_account, &source_state)?;
+    check_account_mint(&token_config, vault, &vault_state)?;
+
+    let escrow = Escrow {
+        depositor: *depositor.key,
+        recipient: *recipient.key,
+        vault: *vault.key,
+        amount,
+        unlock_ts,
+        released: false,
+    };
+    let serialized = escrow.try_to_vec()?;
+
+    create_pda_account(
+        depositor,
+        escrow_account,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[ESCROW_SEED, config_account.key.as_ref(), depositor.key.as_ref(), recipient.key.as_ref(), &[escrow_bump]],
+    )?;
+    escrow_account.try_borrow_mut_data()?.copy_from_slice(&serialized);
 
-    msg!("Transferring {} tokens from {} to {}", amount, from_account.key, to_account.key);
-    
+    msg!("Escrowing {} tokens for {} until {}", amount, recipient.key, unlock_ts);
     let transfer_ix = transfer(
         token_program.key,
-        from_account.key,
-        to_account.key,
-        authority.key,
+        source_account.key,
+        vault.key,
+        depositor.key,
         &[],
         amount,
     )?;
 
-    invoke(&transfer_ix, &[from_account.clone(), to_account.clone(), authority.clone()])?;
+    invoke(&transfer_ix, accounts)?;
     Ok(())
 }
 
-fn process_mint(accounts: &[AccountInfo], instruction_data: &[u8], _program_id: &Pubkey) -> ProgramResult {
-    let amount = decode_amount(instruction_data)?;
+// Anyone may trigger the release once unlock_ts has passed, the tokens can only go to the recipient
+fn process_escrow_release(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let vault = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_token_program(token_program)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut escrow_data = escrow_account.try_borrow_mut_data()?;
+    let mut escrow = Escrow::try_from_slice(&escrow_data).map_err(|_| ProgramError::InvalidAccountData)?;
+
+    let (escrow_address, _) = Pubkey::find_program_address(
+        &[ESCROW_SEED, config_account.key.as_ref(), escrow.depositor.as_ref(), escrow.recipient.as_ref()],
+        program_id,
+    );
+    if *escrow_account.key != escrow_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *vault.key != escrow.vault {
+        msg!("Vault {} does not match the escrow", vault.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow.released {
+        msg!("Escrow has already been released");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let destination_state = Account::unpack(&destination_account.try_borrow_data()?)?;
+    check_account_mint(&token_config, destination_account, &destination_state)?;
+    if destination_state.owner != escrow.recipient {
+        msg!("Destination {} is not owned by the recipient", destination_account.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if now < escrow.unlock_ts {
+        msg!("Escrow is locked until {}", escrow.unlock_ts);
+        return Err(CustomError::TimelockNotElapsed.into());
+    }
+
+    escrow.released = true;
+    escrow.serialize(&mut &mut escrow_data[..])?;
+    drop(escrow_data);
+
+    let authority_bump = check_vault_authority(vault_authority, escrow_account.key, program_id)?;
+
+    msg!("Releasing {} escrowed tokens to {}", escrow.amount, destination_account.key);
+    let transfer_ix = transfer(
+        token_program.key,
+        vault.key,
+        destination_account.key,
+        vault_authority.key,
+        &[],
+        escrow.amount,
+    )?;
+
+    invoke_signed(
+        &transfer_ix,
+        accounts,
+        &[&[VAULT_AUTHORITY_SEED, escrow_account.key.as_ref(), &[authority_bump]]],
+    )?;
+    Ok(())
+}
+
+// Accounts are mint, burn authority, token program and config, then one source account per 8-byte amount, all
+// owned by the signing burn authority. Every balance is checked before the first burn, so a short account fails
+// the batch up front instead of partway through
+fn process_batch_burn(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let chunks = instruction_data.chunks_exact(8);
+    check_data_not_empty(instruction_data)?;
+    if !chunks.remainder().is_empty() {
+        return Err(CustomError::InvalidAmount.into());
+    }
+    let leg_count = chunks.len();
 
     let account_info_iter = &mut accounts.iter();
     let mint_account = next_account_info(account_info_iter)?;
-    let destination_account = next_account_info(account_info_iter)?;
-    let mint_authority = next_account_info(account_info_iter)?;
+    let burn_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let config_account = next_account_info(account_info_iter)?;
+    let burn_accounts = account_info_iter.as_slice();
 
-    // Now we check the authority for the signer that is minting
-    check_signer(mint_authority)?;
+    if burn_accounts.len() != leg_count {
+        msg!("Expected {} accounts to burn from, got {}", leg_count, burn_accounts.len());
+        if burn_accounts.len() > leg_count {
+            return Err(CustomError::TooManyAccounts.into());
+        }
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
 
-    let config_data = config_account.try_borrow_data()?;
-    let token_config: TokenConfig = TokenConfig::try_from_slice(&config_data).map_err(|_| ProgramError::InvalidAccountData)?;
+    check_signer(burn_authority)?;
 
-    if *mint_authority.key != token_config.admin_pubkey {
-        msg!("Unauthorized: Only the admin can mint tokens.");
-        return Err(CustomError::UnauthorizedMint.into());
+    let mut token_config = load_config(config_account, program_id)?;
+    check_not_migrating(&token_config)?;
+    check_config_mint(&token_config, mint_account)?;
+
+    let mut total: u64 = 0;
+    for (burn_account, chunk) in burn_accounts.iter().zip(instruction_data.chunks_exact(8)) {
+        let amount = decode_amount(chunk)?;
+        if amount == 0 {
+            return Err(CustomError::InvalidAmount.into());
+        }
+        total = math::checked_add(total, amount)?;
+        let burn_state = Account::unpack(&burn_account.try_borrow_data()?)?;
+        if burn_state.mint != *mint_account.key {
+            msg!("Cannot burn {} tokens against mint {}", burn_state.mint, mint_account.key);
+            return Err(CustomError::MintMismatch.into());
+        }
+        if burn_state.owner != *burn_authority.key {
+            msg!("{} is not the owner of {}", burn_authority.key, burn_account.key);
+            return Err(CustomError::Unauthorized.into());
+        }
+        if burn_state.amount < amount {
+            msg!("{} holds {} tokens, less than the {} to burn", burn_account.key, burn_state.amount, amount);
+            return Err(CustomError::InvalidAmount.into());
+        }
     }
 
-    let mint_state = Mint::unpack(&mint_account.try_borrow_data()?)?;
-    let current_supply = mint_state.supply;
+    token_config.total_burned = token_config.total_burned.checked_add(total as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    record_breaker_burn(&mut token_config, total, burn_authority.key)?;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
 
-    if current_supply + amount > token_config.max_supply {
-        msg!("Minting would exceed max supply limit of {}", token_config.max_supply);
-        return Err(CustomError::MaxSupplyExceeded.into());
+    log_info!("Burning from {} accounts", leg_count);
+    for (burn_account, chunk) in burn_accounts.iter().zip(instruction_data.chunks_exact(8)) {
+        let burn_ix = burn(
+            token_program.key,
+            burn_account.key,
+            mint_account.key,
+            burn_authority.key,
+            &[],
+            decode_amount(chunk)?,
+        )?;
+
+        invoke(&burn_ix, accounts)?;
     }
+    Ok(())
+}
 
-    msg!("Minting {} tokens to {}", amount, destination_account.key);
-    let mint_ix = mint_to(
+// Burns from the admin's own account and lowers max_supply by the same amount in one instruction
+fn process_burn_and_reduce_cap(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let amount = decode_amount(instruction_data)?;
+    if amount == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let burn_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let history_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let mut token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.config_admin(), "the config admin can reduce the supply cap")?;
+    check_not_migrating(&token_config)?;
+    check_no_timelock(&token_config)?;
+    check_config_mint(&token_config, mint_account)?;
+
+    let burn_state = Account::unpack(&burn_account.try_borrow_data()?)?;
+    if burn_state.mint != *mint_account.key {
+        msg!("Cannot burn {} tokens against mint {}", burn_state.mint, mint_account.key);
+        return Err(CustomError::MintMismatch.into());
+    }
+    if burn_state.owner != *admin.key {
+        msg!("{} is not the owner of {}", admin.key, burn_account.key);
+        return Err(CustomError::Unauthorized.into());
+    }
+
+    let old_max_supply = token_config.max_supply;
+    token_config.max_supply = math::checked_sub(token_config.max_supply, amount)?;
+    token_config.total_burned = token_config.total_burned.checked_add(amount as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    record_breaker_burn(&mut token_config, amount, admin.key)?;
+    token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    AdminEvent::SupplyCapChanged { signer: *admin.key, old_max_supply, new_max_supply: token_config.max_supply }.emit()?;
+    record_supply_change(history_account, config_account, program_id, admin.key, old_max_supply, token_config.max_supply)?;
+
+    log_info!("Burning {} tokens and lowering the cap to {}", amount, token_config.max_supply);
+    let burn_ix = burn(
         token_program.key,
+        burn_account.key,
         mint_account.key,
-        destination_account.key,
-        mint_authority.key,
+        admin.key,
         &[],
         amount,
     )?;
 
-    invoke(&mint_ix, &[mint_account.clone(), destination_account.clone(), mint_authority.clone()])?;
+    invoke(&burn_ix, accounts)?;
     Ok(())
 }
 
-fn process_burn(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
-    let amount = decode_amount(instruction_data)?;
+// An optional ninth data byte set to 1 burns as the account's delegate instead of its owner.
+// When the config account follows the token program and burn_treasury_bps is set, that share of the amount
+// goes to the burn treasury and only the rest is burned
+fn process_burn(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let (amount_data, delegate_mode) = match instruction_data.len() {
+        9 => match instruction_data[8] {
+            0 => (&instruction_data[..8], false),
+            1 => (&instruction_data[..8], true),
+            _ => return Err(ProgramError::InvalidInstructionData),
+        },
+        _ => (instruction_data, false),
+    };
+    let amount = decode_amount(amount_data)?;
 
     let account_info_iter = &mut accounts.iter();
     let burn_account = next_account_info(account_info_iter)?;
@@ -182,21 +4388,464 @@ fn process_burn(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramRes
     let burn_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
+    // Legacy callers pass only the four accounts above and burn the full amount with no config checks. The
+    // migration guard lives in the config, so it only holds back burns that pass one
+    let config = match account_info_iter.next() {
+        Some(config_account) => {
+            let token_config = load_config(config_account, program_id)?;
+            check_not_migrating(&token_config)?;
+            check_config_mint(&token_config, mint_account)?;
+            Some((config_account, token_config))
+        },
+        None => None,
+    };
+
+    let treasury_bps = config.as_ref().map_or(0, |(_, token_config)| token_config.burn_treasury_bps);
+    let treasury_amount = bps_of(amount, treasury_bps)?;
+    let burn_treasury = match &config {
+        Some((_, token_config)) if treasury_amount > 0 => {
+            let burn_treasury = next_account_info(account_info_iter)?;
+            if *burn_treasury.key != token_config.burn_treasury {
+                msg!("Burn treasury {} does not match the configured treasury", burn_treasury.key);
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let treasury_state = Account::unpack(&burn_treasury.try_borrow_data()?)?;
+            if treasury_state.mint != *mint_account.key {
+                msg!("Burn treasury {} does not hold mint {}", burn_treasury.key, mint_account.key);
+                return Err(CustomError::MintMismatch.into());
+            }
+            Some(burn_treasury)
+        },
+        _ => None,
+    };
+    check_no_extra_accounts(account_info_iter)?;
+
     // Let's check the signer if has furn authority, this is a good measure against attack and will help Brett to protect
     check_signer(burn_authority)?;
 
-    msg!("Burning {} tokens from {}", amount, burn_account.key);
+    let burn_state = Account::unpack(&burn_account.try_borrow_data()?)?;
+    if burn_state.mint != *mint_account.key {
+        msg!("Cannot burn {} tokens against mint {}", burn_state.mint, mint_account.key);
+        return Err(CustomError::MintMismatch.into());
+    }
+    if delegate_mode {
+        if burn_state.delegate != COption::Some(*burn_authority.key) {
+            msg!("{} is not the delegate of {}", burn_authority.key, burn_account.key);
+            return Err(CustomError::Unauthorized.into());
+        }
+        if burn_state.delegated_amount < amount {
+            msg!("Delegated allowance of {} is less than {}", burn_state.delegated_amount, amount);
+            return Err(CustomError::InvalidAmount.into());
+        }
+    } else if burn_state.owner != *burn_authority.key {
+        msg!("{} is not the owner of {}", burn_authority.key, burn_account.key);
+        return Err(CustomError::Unauthorized.into());
+    }
+
+    // Only what is actually burned counts, the treasury share stays in circulation
+    let burn_amount = amount - treasury_amount;
+    if let Some((config_account, mut token_config)) = config {
+        token_config.total_burned =
+            token_config.total_burned.checked_add(burn_amount as u128).ok_or(CustomError::ArithmeticOverflow)?;
+        record_breaker_burn(&mut token_config, burn_amount, burn_authority.key)?;
+        token_config.serialize(&mut &mut config_account.try_borrow_mut_data()?[..])?;
+    }
+
+    log_info!("Burning {} tokens from {}", burn_amount, burn_account.key);
     let burn_ix = burn(
         token_program.key,
         burn_account.key,
         mint_account.key,
         burn_authority.key,
         &[],
+        burn_amount,
+    )?;
+
+    invoke(&burn_ix, accounts)?;
+
+    if let Some(burn_treasury) = burn_treasury {
+        log_info!("Redirecting {} tokens of the burn to {}", treasury_amount, burn_treasury.key);
+        let treasury_ix = transfer(
+            token_program.key,
+            burn_account.key,
+            burn_treasury.key,
+            burn_authority.key,
+            &[],
+            treasury_amount,
+        )?;
+        invoke(&treasury_ix, accounts)?;
+    }
+    Ok(())
+}
+
+// Data is ratio_num(8) + ratio_den(8). Set up by the old mint's admin, with a new mint whose mint
+// authority has already been handed to the migration PDA
+fn process_initialize_migration(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    check_data_len(instruction_data, 16)?;
+    let ratio_num = decode_amount(&instruction_data[..8])?;
+    let ratio_den = decode_amount(&instruction_data[8..])?;
+    if ratio_num == 0 || ratio_den == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let config_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+    let migration_account = next_account_info(account_info_iter)?;
+    let old_mint = next_account_info(account_info_iter)?;
+    let new_mint = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    let token_config = load_config(config_account, program_id)?;
+    require_admin(admin, &token_config.mint_admin(), "the mint admin can set up a token migration")?;
+    check_config_mint(&token_config, old_mint)?;
+
+    if old_mint.key == new_mint.key {
+        msg!("A migration needs two different mints");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (migration_address, migration_bump) = Pubkey::find_program_address(
+        &[MIGRATION_SEED, old_mint.key.as_ref(), new_mint.key.as_ref()],
+        program_id,
+    );
+    if *migration_account.key != migration_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !migration_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let new_mint_state = unpack_mint(new_mint)?;
+    if new_mint_state.mint_authority != COption::Some(migration_address) {
+        msg!("New mint {} must have the migration PDA {} as its mint authority", new_mint.key, migration_address);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let migration = TokenMigration {
+        old_mint: *old_mint.key,
+        new_mint: *new_mint.key,
+        ratio_num,
+        ratio_den,
+        initialized: true,
+    };
+    let serialized = migration.try_to_vec()?;
+
+    create_pda_account(
+        admin,
+        migration_account,
+        system_program,
+        program_id,
+        serialized.len(),
+        &[MIGRATION_SEED, old_mint.key.as_ref(), new_mint.key.as_ref(), &[migration_bump]],
+    )?;
+    migration_account.try_borrow_mut_data()?.copy_from_slice(&serialized);
+
+    AdminEvent::MigrationInitialized {
+        signer: *admin.key,
+        old_mint: *old_mint.key,
+        new_mint: *new_mint.key,
+        ratio_num,
+        ratio_den,
+    }.emit()?;
+
+    msg!("Migration from {} to {} open at {}:{}", old_mint.key, new_mint.key, ratio_num, ratio_den);
+    Ok(())
+}
+
+// Burns amount of the old mint from the signing user and mints the ratio-adjusted amount of the new mint
+// to them in the same instruction, so a failure on either side leaves both balances untouched. The configs of
+// both mints follow the token program, and each counts its side of the swap
+fn process_migrate_tokens(accounts: &[AccountInfo], instruction_data: &[u8], program_id: &Pubkey) -> ProgramResult {
+    let amount = decode_amount(instruction_data)?;
+    if amount == 0 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let migration_account = next_account_info(account_info_iter)?;
+    let user = next_account_info(account_info_iter)?;
+    let source_account = next_account_info(account_info_iter)?;
+    let old_mint = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let new_mint = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let old_config_account = next_account_info(account_info_iter)?;
+    let new_config_account = next_account_info(account_info_iter)?;
+    check_no_extra_accounts(account_info_iter)?;
+
+    check_signer(user)?;
+    check_token_program(token_program)?;
+
+    let mut old_config = load_config(old_config_account, program_id)?;
+    check_not_migrating(&old_config)?;
+    check_config_mint(&old_config, old_mint)?;
+    let mut new_config = load_config(new_config_account, program_id)?;
+    check_not_migrating(&new_config)?;
+    check_config_mint(&new_config, new_mint)?;
+
+    if migration_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let migration = TokenMigration::deserialize(&mut &migration_account.try_borrow_data()?[..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    if !migration.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if *old_mint.key != migration.old_mint || *new_mint.key != migration.new_mint {
+        msg!("Mints do not match the migration from {} to {}", migration.old_mint, migration.new_mint);
+        return Err(CustomError::MintMismatch.into());
+    }
+    // The record must sit at the PDA of its own mints, so no other account of this program can pose as one
+    let (migration_address, migration_bump) = Pubkey::find_program_address(
+        &[MIGRATION_SEED, old_mint.key.as_ref(), new_mint.key.as_ref()],
+        program_id,
+    );
+    if *migration_account.key != migration_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let new_amount = math::mul_div(amount, migration.ratio_num, migration.ratio_den)?;
+    if new_amount == 0 {
+        msg!("Migrating {} tokens would mint nothing at {}:{}", amount, migration.ratio_num, migration.ratio_den);
+        return Err(CustomError::InvalidAmount.into());
+    }
+
+    old_config.total_burned = old_config.total_burned.checked_add(amount as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    old_config.serialize(&mut &mut old_config_account.try_borrow_mut_data()?[..])?;
+    new_config.total_minted =
+        new_config.total_minted.checked_add(new_amount as u128).ok_or(CustomError::ArithmeticOverflow)?;
+    new_config.serialize(&mut &mut new_config_account.try_borrow_mut_data()?[..])?;
+
+    log_info!("Migrating {} old tokens from {} into {} new tokens", amount, source_account.key, new_amount);
+    let burn_ix = burn(
+        token_program.key,
+        source_account.key,
+        old_mint.key,
+        user.key,
+        &[],
         amount,
     )?;
+    invoke(&burn_ix, accounts)?;
 
-    invoke(&burn_ix, &[burn_account.clone(), mint_account.clone(), burn_authority.clone()])?;
+    let mint_ix = mint_to(
+        token_program.key,
+        new_mint.key,
+        destination_account.key,
+        migration_account.key,
+        &[],
+        new_amount,
+    )?;
+    invoke_signed(
+        &mint_ix,
+        accounts,
+        &[&[MIGRATION_SEED, old_mint.key.as_ref(), new_mint.key.as_ref(), &[migration_bump]]],
+    )?;
     Ok(())
 }
 
 entrypoint!(process_instruction);
+
+#[cfg(test)]
+mod program_tests;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_len_matches_the_serialized_layout() {
+        assert_eq!(CONFIG_LEN, TokenConfig::default().try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn amount_out_matches_hand_computed_quotes() {
+        // 1000 in at 30 bps leaves 997; 10_000 * 997 / 10_997 = 906.6
+        assert_eq!(get_amount_out(1_000, 10_000, 10_000, 30), Ok(906));
+        assert_eq!(get_amount_out(1_000, 1_000, 1_000, 0), Ok(500));
+        // 250 * 4_000 / (2_000 + 250) = 444.4, with the fee taking nothing off
+        assert_eq!(get_amount_out(250, 2_000, 4_000, 0), Ok(444));
+        // 1 in at 30 bps rounds down to nothing after the fee
+        assert_eq!(get_amount_out(1, 10_000, 10_000, 30), Ok(0));
+        assert_eq!(get_amount_out(1_000, 10_000, 10_000, 10_000), Ok(0));
+        assert_eq!(get_amount_out(0, 10_000, 10_000, 30), Ok(0));
+    }
+
+    #[test]
+    fn amount_out_stays_in_range_at_u64_max() {
+        assert_eq!(get_amount_out(u64::MAX, u64::MAX, u64::MAX, 0), Ok(u64::MAX / 2));
+        assert_eq!(get_amount_out(u64::MAX, 1, u64::MAX, 0), Ok(u64::MAX - 1));
+    }
+
+    #[test]
+    fn amount_out_rejects_empty_reserves_and_fees_past_100_percent() {
+        assert_eq!(get_amount_out(1_000, 0, 10_000, 30), Err(ProgramError::InvalidArgument));
+        assert_eq!(get_amount_out(1_000, 10_000, 0, 30), Err(ProgramError::InvalidArgument));
+        assert_eq!(get_amount_out(1_000, 10_000, 10_000, 10_001), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn varint_amounts_decode_from_their_minimal_encoding() {
+        assert_eq!(decode_amount_varint(&[0x00]), Ok(0));
+        assert_eq!(decode_amount_varint(&[0x7f]), Ok(127));
+        assert_eq!(decode_amount_varint(&[0x80, 0x01]), Ok(128));
+        assert_eq!(decode_amount_varint(&[0xac, 0x02]), Ok(300));
+        // Nine full groups and the one bit left over in the tenth
+        let mut max = [0xff; 10];
+        max[9] = 0x01;
+        assert_eq!(decode_amount_varint(&max), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn varint_amounts_refuse_anything_but_the_minimal_encoding() {
+        let invalid = || Err(CustomError::InvalidAmount.into());
+        // Overlong: a trailing zero group adds nothing
+        assert_eq!(decode_amount_varint(&[0x80, 0x00]), invalid());
+        // Truncated: the last byte still says another follows
+        assert_eq!(decode_amount_varint(&[0x80]), invalid());
+        assert_eq!(decode_amount_varint(&[0xac, 0x82]), invalid());
+        assert_eq!(decode_amount_varint(&[]), invalid());
+        // The tenth group can only hold one bit
+        let mut too_wide = [0xff; 10];
+        too_wide[9] = 0x02;
+        assert_eq!(decode_amount_varint(&too_wide), invalid());
+        // No u64 needs an eleventh byte, even when the tenth group is in range
+        let mut eleven = [0x80; 11];
+        eleven[9] = 0x81;
+        eleven[10] = 0x01;
+        assert_eq!(decode_amount_varint(&eleven), invalid());
+        // Bytes after the terminating one are refused rather than ignored
+        assert_eq!(decode_amount_varint(&[0x01, 0x01]), invalid());
+    }
+
+    #[test]
+    fn both_tag_widths_split_off_the_same_instruction() {
+        // Transfer of 300: the payload is identical, only the tag in front of it differs
+        let payload = 300u64.to_le_bytes();
+        let narrow = [&[0][..], &payload].concat();
+        let wide = [&[0, 0, 0, 0][..], &payload].concat();
+        assert_eq!(split_byte_discriminator(&narrow), Ok((0, &payload[..])));
+        assert_eq!(split_wide_discriminator(&wide), Ok((0, &payload[..])));
+
+        // Read by the other parser, the 1-byte form runs into the amount and reads as a tag past 255
+        assert_eq!(split_wide_discriminator(&narrow), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(split_wide_discriminator(&[0, 0, 0]), Err(ProgramError::InvalidInstructionData));
+        let empty = Err(CustomError::EmptyInstructionData.into());
+        assert_eq!(split_byte_discriminator(&[]), empty);
+        assert_eq!(split_wide_discriminator(&[]), empty);
+    }
+
+    #[test]
+    fn every_dispatched_tag_has_its_own_name() {
+        let names: Vec<&str> = (0..=59).map(instruction_name).collect();
+        assert_eq!(names[0], "Transfer");
+        assert_eq!(names[1], "Mint");
+        assert_eq!(names[59], "DisableMint");
+        for (tag, name) in names.iter().enumerate() {
+            assert_ne!(*name, "Unknown", "tag {} has no name", tag);
+            assert_eq!(names.iter().filter(|other| *other == name).count(), 1, "{} is used twice", name);
+        }
+    }
+
+    #[test]
+    fn tags_past_the_table_are_unknown() {
+        for tag in 60..=u8::MAX {
+            assert_eq!(instruction_name(tag), "Unknown");
+        }
+    }
+
+    fn legacy_config(max_supply: u64) -> (Vec<u8>, Pubkey) {
+        let admin_pubkey = Pubkey::new_unique();
+        let v1 = TokenConfigV1 { max_supply, initialized: true, admin_pubkey };
+        (v1.try_to_vec().unwrap(), admin_pubkey)
+    }
+
+    #[test]
+    fn legacy_config_is_read_by_length_whatever_its_first_byte() {
+        // Low bytes of 1 and 2 would pass for a version byte if the layout were picked by it
+        for max_supply in [0, 1, 2, 258, u64::MAX] {
+            let (mut data, admin_pubkey) = legacy_config(max_supply);
+            assert_eq!(data.len(), LEGACY_CONFIG_LEN);
+
+            let token_config = migrate(&mut data).unwrap();
+            assert_eq!(token_config.version, CONFIG_VERSION);
+            assert_eq!(token_config.max_supply, max_supply);
+            assert!(token_config.initialized);
+            assert_eq!(token_config.admin_pubkey, admin_pubkey);
+        }
+    }
+
+    #[test]
+    fn current_config_round_trips_through_migrate() {
+        let token_config = TokenConfig {
+            version: CONFIG_VERSION,
+            max_supply: 1,
+            initialized: true,
+            mint: Pubkey::new_unique(),
+            ..TokenConfig::default()
+        };
+        let mut data = token_config.try_to_vec().unwrap();
+
+        let migrated = migrate(&mut data).unwrap();
+        assert_eq!(migrated.max_supply, 1);
+        assert_eq!(migrated.mint, token_config.mint);
+    }
+
+    #[test]
+    fn version_2_config_gains_zeroed_supply_counters() {
+        let token_config = TokenConfig {
+            version: 2,
+            max_supply: 1,
+            initialized: true,
+            airdrop_vault: Pubkey::new_unique(),
+            ..TokenConfig::default()
+        };
+        // A version 2 account ends at airdrop_vault; MigrateConfig pads it out before migrating
+        let mut data = token_config.try_to_vec().unwrap();
+        data.truncate(V3_COUNTERS_OFFSET);
+        data.resize(CONFIG_LEN, 0);
+
+        let migrated = migrate(&mut data).unwrap();
+        assert_eq!(migrated.version, CONFIG_VERSION);
+        assert_eq!(migrated.airdrop_vault, token_config.airdrop_vault);
+        assert_eq!((migrated.total_minted, migrated.total_burned), (0, 0));
+    }
+
+    #[test]
+    fn shortest_version_2_config_reads_its_missing_fields_as_zero() {
+        let token_config = TokenConfig {
+            version: 2,
+            max_supply: 1,
+            initialized: true,
+            mint_rewards_bps: 250,
+            rewards_vault: Pubkey::new_unique(),
+            ..TokenConfig::default()
+        };
+        // The first version 2 layout ended at rewards_vault
+        let mut data = token_config.try_to_vec().unwrap();
+        data.truncate(1 + 8 + 1 + 32 + 8 + 8 + 2 + 32);
+        data.resize(CONFIG_LEN, 0);
+
+        let migrated = migrate(&mut data).unwrap();
+        assert_eq!(migrated.version, CONFIG_VERSION);
+        assert_eq!((migrated.mint_rewards_bps, migrated.rewards_vault), (250, token_config.rewards_vault));
+        let expected = TokenConfig { version: CONFIG_VERSION, ..token_config };
+        assert_eq!(migrated.try_to_vec().unwrap(), expected.try_to_vec().unwrap());
+    }
+
+    #[test]
+    fn version_3_config_widens_its_supply_counters() {
+        let token_config = TokenConfig { version: 3, max_supply: 1, initialized: true, ..TokenConfig::default() };
+        let mut data = token_config.try_to_vec().unwrap();
+        data.truncate(V3_COUNTERS_OFFSET);
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.resize(CONFIG_LEN, 0);
+
+        let migrated = migrate(&mut data).unwrap();
+        assert_eq!(migrated.version, CONFIG_VERSION);
+        assert_eq!(migrated.max_supply, 1);
+        assert_eq!((migrated.total_minted, migrated.total_burned), (u64::MAX as u128, 7));
+    }
+}
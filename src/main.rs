@@ -25,6 +25,7 @@ pub struct TokenConfig {
 
 pub enum CustomError {
     MaxSupplyExceeded = 0x1,
+    InvalidAmount = 0x3,
 }
 
 impl From<CustomError> for ProgramError {
@@ -63,6 +64,23 @@ fn process_instruction(
     }
 }
 
+// Same contract as decode_amount in lib.rs: the payload must be exactly 8 bytes
+fn decode_amount(data: &[u8]) -> Result<u64, ProgramError> {
+    if data.len() != 8 {
+        return Err(CustomError::InvalidAmount.into());
+    }
+    Ok(u64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?))
+}
+
+// Same check as lib.rs: refuse to reach the CPI without the authority's signature
+fn check_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        msg!("Missing required signature for account: {}", account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
 fn process_initialize(
     accounts: &[AccountInfo],
     admin_pubkey: Pubkey,
@@ -96,7 +114,7 @@ fn process_transfer(accounts: &[AccountInfo], instruction_data: &[u8]) -> Progra
     let authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
-    let amount = u64::from_le_bytes(instruction_data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let amount = decode_amount(instruction_data)?;
     if amount == 0 {
         return Err(ProgramError::InvalidInstructionData);
     }
@@ -123,7 +141,7 @@ fn process_mint(accounts: &[AccountInfo], instruction_data: &[u8], _program_id:
     let token_program = next_account_info(account_info_iter)?;
     let config_account = next_account_info(account_info_iter)?;
 
-    let amount = u64::from_le_bytes(instruction_data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    let amount = decode_amount(instruction_data)?;
 
     let config_data = config_account.try_borrow_data()?;
     let token_config: TokenConfig = TokenConfig::try_from_slice(&config_data).map_err(|_| ProgramError::InvalidAccountData)?;
@@ -162,7 +180,9 @@ fn process_burn(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramRes
     let burn_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
-    let amount = u64::from_le_bytes(instruction_data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    check_signer(burn_authority)?;
+
+    let amount = decode_amount(instruction_data)?;
 
     msg!("Burning {} tokens", amount);
     let burn_ix = burn(
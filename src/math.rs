@@ -0,0 +1,63 @@
+use std::convert::TryFrom;
+
+use solana_program::program_error::ProgramError;
+
+use crate::error::CustomError;
+
+// a * b / denom with a u128 intermediate, so the product can't overflow before the division.
+// Rounds down; a zero denominator or a result past u64::MAX is an ArithmeticOverflow
+pub fn mul_div(a: u64, b: u64, denom: u64) -> Result<u64, ProgramError> {
+    let scaled = (a as u128)
+        .checked_mul(b as u128)
+        .ok_or(CustomError::ArithmeticOverflow)?
+        .checked_div(denom as u128)
+        .ok_or(CustomError::ArithmeticOverflow)?;
+    Ok(u64::try_from(scaled).map_err(|_| CustomError::ArithmeticOverflow)?)
+}
+
+pub fn checked_add(a: u64, b: u64) -> Result<u64, ProgramError> {
+    Ok(a.checked_add(b).ok_or(CustomError::ArithmeticOverflow)?)
+}
+
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    Ok(a.checked_sub(b).ok_or(CustomError::ArithmeticOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflow() -> ProgramError {
+        CustomError::ArithmeticOverflow.into()
+    }
+
+    #[test]
+    fn mul_div_rounds_down() {
+        assert_eq!(mul_div(10, 1, 3), Ok(3));
+        assert_eq!(mul_div(2, 1, 3), Ok(0));
+        assert_eq!(mul_div(9_999, 25, 10_000), Ok(24));
+        assert_eq!(mul_div(0, u64::MAX, 1), Ok(0));
+    }
+
+    #[test]
+    fn mul_div_keeps_products_past_u64() {
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 2, 4), Ok(u64::MAX / 2));
+    }
+
+    #[test]
+    fn mul_div_overflows_on_zero_denominator_or_large_result() {
+        assert_eq!(mul_div(1, 1, 0), Err(overflow()));
+        assert_eq!(mul_div(0, 0, 0), Err(overflow()));
+        assert_eq!(mul_div(u64::MAX, 2, 1), Err(overflow()));
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX - 1), Err(overflow()));
+    }
+
+    #[test]
+    fn checked_add_and_sub_stop_at_the_bounds() {
+        assert_eq!(checked_add(u64::MAX - 1, 1), Ok(u64::MAX));
+        assert_eq!(checked_add(u64::MAX, 1), Err(overflow()));
+        assert_eq!(checked_sub(1, 1), Ok(0));
+        assert_eq!(checked_sub(0, 1), Err(overflow()));
+    }
+}
@@ -0,0 +1,317 @@
+use super::*;
+use crate::{TokenConfigV1, CONFIG_LEN, CONFIG_VERSION, OPT_IN_SEED, WHITELIST_SEED};
+
+const DELAY: i64 = 1_000;
+
+// One instruction for each privileged change that exists outside UpdateConfig and so has no queued form
+async fn unqueueable_changes(env: &mut TestEnv) -> Vec<Instruction> {
+    let admin = env.admin.pubkey();
+    let burn_account = env.funded_account(&admin, 10).await;
+    let history = env.supply_history().await;
+    let key = Pubkey::new_unique();
+    let mut roles = admin.to_bytes().to_vec();
+    roles.extend_from_slice(admin.as_ref());
+    let mut exemptions = vec![1];
+    exemptions.extend_from_slice(key.as_ref());
+    let mut admin_signers = vec![1];
+    admin_signers.extend_from_slice(admin.as_ref());
+
+    vec![
+        env.ix(42, key.as_ref(), vec![writable(env.config), signer(admin)]),
+        env.ix(52, &exemptions, vec![writable(env.config), signer(admin)]),
+        env.ix(20, &admin_signers, vec![writable(env.config), signer(admin)]),
+        env.ix(34, &5u64.to_le_bytes(), vec![
+            writable(env.config),
+            signer(admin),
+            writable(burn_account),
+            writable(env.mint),
+            readonly(spl_token::id()),
+            writable(history),
+        ]),
+        env.ix(36, &roles, vec![writable(env.config), signer(admin)]),
+        // Last, since it hands the mint role on to someone else
+        env.ix(29, key.as_ref(), vec![
+            writable(env.config),
+            writable(env.mint),
+            signer(admin),
+            readonly(spl_token::id()),
+        ]),
+    ]
+}
+
+#[tokio::test]
+async fn timelock_refuses_changes_that_cannot_be_queued() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.update_config(7, &DELAY.to_le_bytes()).await.unwrap();
+
+    for ix in unqueueable_changes(&mut env).await {
+        assert_custom_error(env.send(&[ix], &[]).await, CustomError::TimelockNotElapsed);
+    }
+    let config = env.config().await;
+    assert_eq!(config.mint_admin, Pubkey::default());
+    assert_eq!(config.max_supply, MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn lifting_the_timelock_waits_out_the_delay() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.set_time(0).await;
+    env.update_config(7, &DELAY.to_le_bytes()).await.unwrap();
+
+    let admin = env.admin.pubkey();
+    let mut payload = vec![7];
+    payload.extend_from_slice(&0i64.to_le_bytes());
+    let queue_ix = env.ix(21, &payload, vec![writable(env.config), signer(admin)]);
+    env.send(&[queue_ix], &[]).await.unwrap();
+
+    let execute_ix = env.ix(22, &[], vec![writable(env.config), signer(admin)]);
+    assert_custom_error(env.send(std::slice::from_ref(&execute_ix), &[]).await, CustomError::TimelockNotElapsed);
+
+    env.set_time(DELAY).await;
+    env.send(&[execute_ix], &[]).await.unwrap();
+    for ix in unqueueable_changes(&mut env).await {
+        env.send(&[ix], &[]).await.unwrap();
+    }
+    let config = env.config().await;
+    assert_eq!(config.max_supply, MAX_SUPPLY - 5);
+    assert_eq!(config.config_admin, admin);
+}
+
+// A wallet's record under seed for the whitelist and opt-in instructions, which share one account layout
+fn wallet_record_ix(env: &TestEnv, tag: u8, seed: &[u8], wallet: Pubkey, value: bool) -> Instruction {
+    let (record, _) =
+        Pubkey::find_program_address(&[seed, env.config.as_ref(), wallet.as_ref()], &env.program_id);
+    env.ix(tag, &[value as u8], vec![
+        readonly(env.config),
+        signer(env.admin.pubkey()),
+        readonly(wallet),
+        writable(record),
+        readonly(system_program::id()),
+    ])
+}
+
+#[tokio::test]
+async fn wallet_and_queued_changes_are_logged() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.set_time(0).await;
+    env.update_config(7, &DELAY.to_le_bytes()).await.unwrap();
+    let admin = env.admin.pubkey();
+    let wallet = Pubkey::new_unique();
+
+    let ix = wallet_record_ix(&env, 33, WHITELIST_SEED, wallet, true);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::WhitelistChanged { signer: admin, wallet, whitelisted: true }]);
+
+    let ix = wallet_record_ix(&env, 31, OPT_IN_SEED, wallet, true);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::OptInExemptChanged { signer: admin, wallet, exempt: true }]);
+
+    let mut payload = vec![0];
+    payload.extend_from_slice(&9u64.to_le_bytes());
+    let ix = env.ix(21, &payload, vec![writable(env.config), signer(admin)]);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::ActionQueued {
+        signer: admin,
+        field: 0,
+        value: 9u64.to_le_bytes().to_vec(),
+        earliest_exec_ts: DELAY,
+    }]);
+}
+
+#[tokio::test]
+async fn migrating_the_config_is_logged() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+
+    let ix = env.ix(49, &[], vec![writable(env.config), signer(admin)]);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::ConfigMigrated {
+        signer: admin,
+        old_version: CONFIG_VERSION,
+        new_version: CONFIG_VERSION,
+    }]);
+}
+
+// A config as the first release wrote it: a bare TokenConfigV1 with the admin, at a keypair address
+async fn install_first_release_config(env: &mut TestEnv, max_supply: u64) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let data = TokenConfigV1 { max_supply, initialized: true, admin_pubkey: env.admin.pubkey() }.try_to_vec().unwrap();
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    let account = SdkAccount {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: env.program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    env.ctx.set_account(&address, &account.into());
+    address
+}
+
+fn migrate_config_ix(env: &TestEnv, config: Pubkey, mint: Pubkey) -> Instruction {
+    let admin = env.admin.pubkey();
+    env.ix(49, &[], vec![
+        writable(config),
+        signer(admin),
+        readonly(mint),
+        signer(admin),
+        readonly(system_program::id()),
+    ])
+}
+
+#[tokio::test]
+async fn migrating_a_first_release_config_grows_it_to_the_current_layout() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let holder = env.funded_account(&admin, 500).await;
+    env.config = install_first_release_config(&mut env, MAX_SUPPLY).await;
+
+    let ix = migrate_config_ix(&env, env.config, env.mint);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::ConfigMigrated { signer: admin, old_version: 1, new_version: CONFIG_VERSION }]);
+
+    let config = env.config;
+    let account = env.account(&config).await.unwrap();
+    assert_eq!(account.data.len(), CONFIG_LEN);
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(account.lamports, CONFIG_LEN));
+
+    // Everything the first release didn't have reads as disabled; the mint is bound and its supply counted
+    let expected = TokenConfig {
+        version: CONFIG_VERSION,
+        max_supply: MAX_SUPPLY,
+        initialized: true,
+        admin_pubkey: admin,
+        mint: env.mint,
+        decimals: DECIMALS,
+        total_minted: 500,
+        ..TokenConfig::default()
+    };
+    assert_eq!(account.data, expected.try_to_vec().unwrap());
+    assert_eq!(env.balance(&holder).await, 500);
+}
+
+#[tokio::test]
+async fn first_release_config_keeps_working_once_migrated() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.config = install_first_release_config(&mut env, MAX_SUPPLY).await;
+
+    // It recorded no mint, so nothing can be minted against it until the migration binds one
+    assert!(env.mint_to(&destination, 100).await.is_err());
+    let foreign_mint = env.create_mint(&Pubkey::new_unique(), DECIMALS).await;
+    let ix = migrate_config_ix(&env, env.config, foreign_mint);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::UnauthorizedMint);
+
+    let ix = migrate_config_ix(&env, env.config, env.mint);
+    env.send(&[ix], &[]).await.unwrap();
+    let config = env.config().await;
+    assert_eq!((config.mint, config.bump), (env.mint, 0));
+
+    env.mint_to(&destination, 100).await.unwrap();
+    env.update_config(6, &50u64.to_le_bytes()).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 100);
+    assert_eq!(env.config().await.max_transfer_amount, 50);
+}
+
+#[tokio::test]
+async fn short_version_2_config_is_padded_out_by_the_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let written = TokenConfig {
+        version: 2,
+        max_supply: MAX_SUPPLY,
+        initialized: true,
+        admin_pubkey: admin,
+        mint_rewards_bps: 250,
+        ..TokenConfig::default()
+    };
+    // Written before bump, mint and everything after them existed, up to and including rewards_vault
+    let mut data = written.try_to_vec().unwrap();
+    data.truncate(1 + 8 + 1 + 32 + 8 + 8 + 2 + 32);
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    let account = SdkAccount {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: env.program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    env.config = Pubkey::new_unique();
+    env.ctx.set_account(&env.config, &account.into());
+
+    let ix = migrate_config_ix(&env, env.config, env.mint);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::ConfigMigrated { signer: admin, old_version: 2, new_version: CONFIG_VERSION }]);
+    let config = env.config().await;
+    assert_eq!((config.mint_rewards_bps, config.mint), (250, env.mint));
+    assert_eq!((config.max_transfer_amount, config.transfer_fee_bps, config.timelock_delay), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn config_updates_need_the_config_admin_and_a_known_field() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let outsider = Keypair::new();
+
+    let ix = env.ix(4, &[6, 1, 0, 0, 0, 0, 0, 0, 0], vec![writable(env.config), signer(outsider.pubkey())]);
+    assert_custom_error(env.send(&[ix], &[&outsider]).await, CustomError::Unauthorized);
+
+    let result = env.update_config(u8::MAX, &[0]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+    assert_custom_error(env.update_config(6, &[1]).await, CustomError::InvalidAmount);
+    assert_eq!(env.config().await.max_transfer_amount, 0);
+}
+
+#[tokio::test]
+async fn exempt_recipients_skip_the_opt_in() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 100).await;
+    let recipient = Pubkey::new_unique();
+    let destination = env.token_account(&recipient).await;
+    let (opt_in, _) =
+        Pubkey::find_program_address(&[OPT_IN_SEED, env.config.as_ref(), recipient.as_ref()], &env.program_id);
+    env.update_config(12, &[1]).await.unwrap();
+    let exempt_ix = |env: &TestEnv, exempt: u8, admin: Pubkey| {
+        env.ix(31, &[exempt], vec![
+            readonly(env.config),
+            signer(admin),
+            readonly(recipient),
+            writable(opt_in),
+            readonly(system_program::id()),
+        ])
+    };
+    let transfer_ix = env.ix(0, &10u64.to_le_bytes(), vec![
+        writable(source),
+        writable(destination),
+        signer(owner.pubkey()),
+        readonly(spl_token::id()),
+        readonly(env.config),
+        readonly(opt_in),
+    ]);
+
+    let outsider = Keypair::new();
+    let result = env.send(&[exempt_ix(&env, 1, outsider.pubkey())], &[&outsider]).await;
+    assert_custom_error(result, CustomError::Unauthorized);
+
+    env.send(&[exempt_ix(&env, 1, env.admin.pubkey())], &[]).await.unwrap();
+    env.send(std::slice::from_ref(&transfer_ix), &[&owner]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 10);
+
+    env.send(&[exempt_ix(&env, 0, env.admin.pubkey())], &[]).await.unwrap();
+    assert_custom_error(env.send(&[transfer_ix], &[&owner]).await, CustomError::RecipientNotOptedIn);
+}
+
+#[tokio::test]
+async fn prefunded_opt_in_pda_is_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let wallet = Pubkey::new_unique();
+    let (record, _) =
+        Pubkey::find_program_address(&[OPT_IN_SEED, env.config.as_ref(), wallet.as_ref()], &env.program_id);
+    env.prefund(&record, 1);
+
+    let ix = wallet_record_ix(&env, 31, OPT_IN_SEED, wallet, true);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.account(&record).await.unwrap().owner, env.program_id);
+}
@@ -0,0 +1,113 @@
+use super::*;
+use crate::{CLAIMS_SEED, VAULT_AUTHORITY_SEED};
+use solana_program::keccak;
+
+const FUNDED: u64 = 1_000;
+
+struct Airdrop {
+    claimant: Keypair,
+    amount: u64,
+    // The other leaf of the two-leaf tree, which is the whole proof for the claimant's leaf
+    sibling: [u8; 32],
+    root: [u8; 32],
+    vault: Pubkey,
+    authority: Pubkey,
+    destination: Pubkey,
+}
+
+fn leaf(index: u64, claimant: &Pubkey, amount: u64) -> [u8; 32] {
+    keccak::hashv(&[&index.to_le_bytes(), claimant.as_ref(), &amount.to_le_bytes()]).to_bytes()
+}
+
+// A two-leaf airdrop paying 100 tokens to the claimant at index 0, out of a registered vault
+async fn open_airdrop(env: &mut TestEnv) -> Airdrop {
+    let claimant = env.funded_wallet().await;
+    let amount = 100;
+    let own_leaf = leaf(0, &claimant.pubkey(), amount);
+    let sibling = leaf(1, &Pubkey::new_unique(), 50);
+    let root = if own_leaf <= sibling {
+        keccak::hashv(&[&own_leaf, &sibling]).to_bytes()
+    } else {
+        keccak::hashv(&[&sibling, &own_leaf]).to_bytes()
+    };
+
+    let (authority, _) = Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, env.config.as_ref()], &env.program_id);
+    let vault = env.funded_account(&authority, FUNDED).await;
+    env.update_config(3, &root).await.unwrap();
+    env.update_config(25, vault.as_ref()).await.unwrap();
+    let destination = env.token_account(&claimant.pubkey()).await;
+    Airdrop { claimant, amount, sibling, root, vault, authority, destination }
+}
+
+fn claim_ix(env: &TestEnv, airdrop: &Airdrop, amount: u64, vault: Pubkey, token_program: Pubkey) -> Instruction {
+    let (bitmap, _) =
+        Pubkey::find_program_address(&[CLAIMS_SEED, env.config.as_ref(), &airdrop.root], &env.program_id);
+    let mut payload = 0u64.to_le_bytes().to_vec();
+    payload.extend_from_slice(&amount.to_le_bytes());
+    payload.extend_from_slice(&airdrop.sibling);
+    env.ix(14, &payload, vec![
+        readonly(env.config),
+        writable(bitmap),
+        signer(airdrop.claimant.pubkey()),
+        writable(vault),
+        readonly(airdrop.authority),
+        writable(airdrop.destination),
+        readonly(token_program),
+        readonly(system_program::id()),
+    ])
+}
+
+async fn claim(env: &mut TestEnv, airdrop: &Airdrop, amount: u64) -> TestResult {
+    let ix = claim_ix(env, airdrop, amount, airdrop.vault, spl_token::id());
+    env.send(&[ix], &[&airdrop.claimant]).await
+}
+
+#[tokio::test]
+async fn claim_pays_out_once() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let airdrop = open_airdrop(&mut env).await;
+
+    claim(&mut env, &airdrop, airdrop.amount).await.unwrap();
+    assert_eq!(env.balance(&airdrop.destination).await, airdrop.amount);
+
+    assert_custom_error(claim(&mut env, &airdrop, airdrop.amount).await, CustomError::AlreadyClaimed);
+    assert_eq!(env.balance(&airdrop.vault).await, FUNDED - airdrop.amount);
+}
+
+#[tokio::test]
+async fn claim_rejects_a_forged_proof() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let airdrop = open_airdrop(&mut env).await;
+
+    assert_custom_error(claim(&mut env, &airdrop, airdrop.amount + 1).await, CustomError::InvalidProof);
+    assert_eq!(env.balance(&airdrop.destination).await, 0);
+}
+
+#[tokio::test]
+async fn claim_pays_only_from_the_registered_vault() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let airdrop = open_airdrop(&mut env).await;
+
+    // Held by the same authority, but it backs something other than the airdrop
+    let other_vault = env.funded_account(&airdrop.authority, FUNDED).await;
+    let ix = claim_ix(&env, &airdrop, airdrop.amount, other_vault, spl_token::id());
+    let result = env.send(&[ix], &[&airdrop.claimant]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+
+    let ix = claim_ix(&env, &airdrop, airdrop.amount, airdrop.vault, Pubkey::new_unique());
+    let result = env.send(&[ix], &[&airdrop.claimant]).await;
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+
+    assert_eq!(env.balance(&other_vault).await, FUNDED);
+    assert_eq!(env.balance(&airdrop.vault).await, FUNDED);
+}
+
+#[tokio::test]
+async fn claims_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let airdrop = open_airdrop(&mut env).await;
+    env.update_config(23, &[1]).await.unwrap();
+
+    assert_custom_error(claim(&mut env, &airdrop, airdrop.amount).await, CustomError::MigrationInProgress);
+    assert_eq!(env.balance(&airdrop.vault).await, FUNDED);
+}
@@ -0,0 +1,101 @@
+use std::convert::TryInto;
+
+use super::*;
+use crate::UNLIMITED_SUPPLY;
+
+// Supply, tracked supply and discrepancy, as AuditSupply returns them
+async fn audit(env: &mut TestEnv) -> (u64, u64, i64, Vec<String>) {
+    let ix = env.ix(56, &[], vec![readonly(env.config), readonly(env.mint)]);
+    let (data, logs) = env.simulate(ix).await;
+    assert_eq!(data.len(), 24);
+    let supply = u64::from_le_bytes(data[..8].try_into().unwrap());
+    let tracked = u64::from_le_bytes(data[8..16].try_into().unwrap());
+    let discrepancy = i64::from_le_bytes(data[16..].try_into().unwrap());
+    (supply, tracked, discrepancy, logs)
+}
+
+fn warned(logs: &[String]) -> bool {
+    logs.iter().any(|line| line.contains("Warning"))
+}
+
+#[tokio::test]
+async fn counters_follow_mints_and_burns() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let account = env.funded_account(&admin, 100).await;
+
+    let burn_ix = env.ix(2, &30u64.to_le_bytes(), vec![
+        writable(account),
+        writable(env.mint),
+        signer(admin),
+        readonly(spl_token::id()),
+        writable(env.config),
+    ]);
+    let batch_burn_ix = env.ix(45, &10u64.to_le_bytes(), vec![
+        writable(env.mint),
+        signer(admin),
+        readonly(spl_token::id()),
+        writable(env.config),
+        writable(account),
+    ]);
+    env.send(&[burn_ix, batch_burn_ix], &[]).await.unwrap();
+
+    let config = env.config().await;
+    assert_eq!((config.total_minted, config.total_burned), (100, 40));
+    let (supply, tracked, discrepancy, logs) = audit(&mut env).await;
+    assert_eq!((supply, tracked, discrepancy), (60, 60, 0));
+    assert!(!warned(&logs));
+}
+
+#[tokio::test]
+async fn minting_around_the_program_is_reported() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let account = env.funded_account(&admin, 100).await;
+
+    let direct_ix = spl_token::instruction::mint_to(&spl_token::id(), &env.mint, &account, &admin, &[], 25).unwrap();
+    env.send(&[direct_ix], &[]).await.unwrap();
+
+    let (supply, tracked, discrepancy, logs) = audit(&mut env).await;
+    assert_eq!((supply, tracked, discrepancy), (125, 100, 25));
+    assert!(warned(&logs));
+}
+
+#[tokio::test]
+async fn counters_run_past_u64_max_and_audit_saturates() {
+    let mut env = TestEnv::new(UNLIMITED_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let account = env.token_account(&admin).await;
+
+    // A token that has already cycled nearly u64::MAX through mints and burns
+    let mut config = env.config().await;
+    config.total_minted = u64::MAX as u128 - 50;
+    config.total_burned = u64::MAX as u128 - 50;
+    env.write_config(&config).await;
+
+    for _ in 0..5 {
+        env.mint_to(&account, 40).await.unwrap();
+    }
+    let burn_ix = env.ix(2, &30u64.to_le_bytes(), vec![
+        writable(account),
+        writable(env.mint),
+        signer(admin),
+        readonly(spl_token::id()),
+        writable(env.config),
+    ]);
+    env.send(&[burn_ix], &[]).await.unwrap();
+
+    let mut config = env.config().await;
+    assert_eq!(config.total_minted, u64::MAX as u128 + 150);
+    assert_eq!(config.total_burned, u64::MAX as u128 - 20);
+    let (supply, tracked, discrepancy, logs) = audit(&mut env).await;
+    assert_eq!((supply, tracked, discrepancy), (170, 170, 0));
+    assert!(!warned(&logs));
+
+    // Counters that alone exceed what the report can hold saturate instead of wrapping
+    config.total_burned = 0;
+    env.write_config(&config).await;
+    let (_, tracked, discrepancy, logs) = audit(&mut env).await;
+    assert_eq!((tracked, discrepancy), (u64::MAX, i64::MIN));
+    assert!(warned(&logs));
+}
@@ -0,0 +1,286 @@
+use super::*;
+use crate::{SupplyChange, SUPPLY_HISTORY_LEN};
+
+fn burn_ix(env: &TestEnv, source: &Pubkey, amount: u64) -> Instruction {
+    env.ix(2, &amount.to_le_bytes(), vec![
+        writable(*source),
+        writable(env.mint),
+        signer(env.admin.pubkey()),
+        readonly(spl_token::id()),
+        writable(env.config),
+    ])
+}
+
+fn batch_burn_ix(env: &TestEnv, legs: &[(Pubkey, u64)]) -> Instruction {
+    let payload: Vec<u8> = legs.iter().flat_map(|(_, amount)| amount.to_le_bytes()).collect();
+    let mut accounts = vec![
+        writable(env.mint),
+        signer(env.admin.pubkey()),
+        readonly(spl_token::id()),
+        writable(env.config),
+    ];
+    accounts.extend(legs.iter().map(|(source, _)| writable(*source)));
+    env.ix(45, &payload, accounts)
+}
+
+#[tokio::test]
+async fn burns_reduce_supply() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let first = env.funded_account(&admin, 100).await;
+    let second = env.funded_account(&admin, 100).await;
+
+    let ix = burn_ix(&env, &first, 40);
+    env.send(&[ix], &[]).await.unwrap();
+    let ix = batch_burn_ix(&env, &[(first, 10), (second, 50)]);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&first).await, 50);
+    assert_eq!(env.balance(&second).await, 50);
+    assert_eq!(env.supply().await, 100);
+}
+
+#[tokio::test]
+async fn burn_takes_the_config_optionally() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    env.update_config(21, &1_000u16.to_le_bytes()).await.unwrap();
+
+    // The old four-account form burns the full amount and leaves the config alone
+    let mut ix = burn_ix(&env, &source, 40);
+    ix.accounts.pop();
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.supply().await, 60);
+    assert_eq!(env.config().await.total_burned, 0);
+
+    // With the config its checks apply, here the burn treasury it now asks for
+    let result = env.send(&[burn_ix(&env, &source, 40)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::NotEnoughAccountKeys);
+    env.update_config(21, &0u16.to_le_bytes()).await.unwrap();
+    env.send(&[burn_ix(&env, &source, 40)], &[]).await.unwrap();
+    assert_eq!(env.supply().await, 20);
+    assert_eq!(env.config().await.total_burned, 40);
+}
+
+#[tokio::test]
+async fn burns_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    env.update_config(23, &[1]).await.unwrap();
+
+    let ix = burn_ix(&env, &source, 40);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MigrationInProgress);
+    let ix = batch_burn_ix(&env, &[(source, 40)]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MigrationInProgress);
+    let ix = reduce_cap_ix(&mut env, &source, 40).await;
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MigrationInProgress);
+    assert_eq!(env.supply().await, 100);
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn burn_needs_the_owner_or_an_allowed_delegate() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 100).await;
+    let delegate = Keypair::new();
+    let ix = spl_token::instruction::approve(&spl_token::id(), &source, &delegate.pubkey(), &owner.pubkey(), &[], 30)
+        .unwrap();
+    env.send(&[ix], &[&owner]).await.unwrap();
+    let delegate_burn_ix = |env: &TestEnv, amount: u64, mode: u8| {
+        let mut ix = burn_ix(env, &source, amount);
+        ix.data.push(mode);
+        ix.accounts[2] = signer(delegate.pubkey());
+        ix
+    };
+
+    // The delegate isn't the owner, so it has to ask for delegate mode
+    let result = env.send(&[delegate_burn_ix(&env, 10, 0)], &[&delegate]).await;
+    assert_custom_error(result, CustomError::Unauthorized);
+    let result = env.send(&[delegate_burn_ix(&env, 31, 1)], &[&delegate]).await;
+    assert_custom_error(result, CustomError::InvalidAmount);
+    let result = env.send(&[delegate_burn_ix(&env, 10, 2)], &[&delegate]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+
+    env.send(&[delegate_burn_ix(&env, 30, 1)], &[&delegate]).await.unwrap();
+    assert_eq!(env.balance(&source).await, 70);
+
+    // Nor can the admin burn from an account it doesn't own
+    let ix = burn_ix(&env, &source, 10);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::Unauthorized);
+}
+
+#[tokio::test]
+async fn burn_refuses_another_mints_account() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    let source = env.create_token_account(&other_mint, &admin).await;
+
+    let ix = burn_ix(&env, &source, 10);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MintMismatch);
+    let ix = batch_burn_ix(&env, &[(source, 10)]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MintMismatch);
+}
+
+#[tokio::test]
+async fn burn_treasury_keeps_its_share_in_circulation() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    let treasury = env.token_account(&admin).await;
+    env.update_config(21, &2_500u16.to_le_bytes()).await.unwrap();
+    env.update_config(22, treasury.as_ref()).await.unwrap();
+
+    let ix = burn_ix(&env, &source, 40);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::NotEnoughAccountKeys);
+
+    let mut ix = burn_ix(&env, &source, 40);
+    ix.accounts.push(writable(treasury));
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&source).await, 60);
+    assert_eq!(env.balance(&treasury).await, 10);
+    assert_eq!(env.supply().await, 70);
+    assert_eq!(env.config().await.total_burned, 30);
+
+    let mut ix = burn_ix(&env, &source, 40);
+    ix.accounts.push(writable(source));
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn batch_burn_checks_every_leg_before_burning() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let first = env.funded_account(&admin, 100).await;
+    let second = env.funded_account(&admin, 100).await;
+
+    let ix = batch_burn_ix(&env, &[(first, 50), (second, 101)]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InvalidAmount);
+    let ix = batch_burn_ix(&env, &[(first, 50), (second, 0)]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InvalidAmount);
+    let mut ix = batch_burn_ix(&env, &[(first, 50), (second, 50)]);
+    ix.accounts.pop();
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::NotEnoughAccountKeys);
+    let mut ix = batch_burn_ix(&env, &[(first, 50), (second, 50)]);
+    ix.accounts.push(writable(first));
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::TooManyAccounts);
+    assert_eq!(env.supply().await, 200);
+
+    let owner = env.funded_wallet().await;
+    let foreign = env.funded_account(&owner.pubkey(), 100).await;
+    let ix = batch_burn_ix(&env, &[(first, 50), (foreign, 50)]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::Unauthorized);
+    assert_eq!(env.balance(&first).await, 100);
+}
+
+async fn reduce_cap_ix(env: &mut TestEnv, source: &Pubkey, amount: u64) -> Instruction {
+    let history = env.supply_history().await;
+    env.ix(34, &amount.to_le_bytes(), vec![
+        writable(env.config),
+        signer(env.admin.pubkey()),
+        writable(*source),
+        writable(env.mint),
+        readonly(spl_token::id()),
+        writable(history),
+    ])
+}
+
+#[tokio::test]
+async fn burn_and_reduce_cap_lowers_the_cap_by_the_burn() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+
+    let ix = reduce_cap_ix(&mut env, &source, 0).await;
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InvalidAmount);
+    let ix = reduce_cap_ix(&mut env, &source, 40).await;
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY - 40);
+    assert_eq!(env.supply().await, 60);
+}
+
+#[tokio::test]
+async fn supply_history_keeps_the_latest_cap_changes_in_order() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    let history = env.supply_history().await;
+
+    let changes = SUPPLY_HISTORY_LEN as u64 + 2;
+    for n in 1..=changes {
+        env.set_time(n as i64).await;
+        let ix = reduce_cap_ix(&mut env, &source, n).await;
+        env.send(&[ix], &[]).await.unwrap();
+    }
+
+    let ix = env.ix(58, &[], vec![readonly(env.config), readonly(history)]);
+    let (return_data, _) = env.simulate(ix).await;
+    let recorded = Vec::<SupplyChange>::try_from_slice(&return_data).unwrap();
+    assert_eq!(recorded.len(), SUPPLY_HISTORY_LEN);
+    let mut cap = MAX_SUPPLY - (1..=2).sum::<u64>();
+    for (change, n) in recorded.iter().zip(3..=changes) {
+        assert_eq!(*change, SupplyChange {
+            old_max_supply: cap,
+            new_max_supply: cap - n,
+            timestamp: n as i64,
+            signer: admin,
+        });
+        cap -= n;
+    }
+    assert_eq!(env.config().await.max_supply, cap);
+
+    let ix = env.ix(57, &[], vec![
+        readonly(env.config),
+        signer(admin),
+        writable(history),
+        readonly(system_program::id()),
+    ]);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::AccountAlreadyInitialized);
+}
+
+#[tokio::test]
+async fn reducing_the_cap_is_refused_under_a_timelock() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    env.update_config(7, &60i64.to_le_bytes()).await.unwrap();
+
+    let ix = reduce_cap_ix(&mut env, &source, 10).await;
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::TimelockNotElapsed);
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn burn_breaker_pauses_after_heavy_burning_in_its_window() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 1_000).await;
+    env.update_config(29, &100u64.to_le_bytes()).await.unwrap();
+    env.update_config(30, &60i64.to_le_bytes()).await.unwrap();
+
+    // 60 and then 50 a minute apart land in separate windows
+    env.set_time(1_000).await;
+    env.send(&[burn_ix(&env, &source, 60)], &[]).await.unwrap();
+    env.set_time(1_060).await;
+    env.send(&[batch_burn_ix(&env, &[(source, 50)])], &[]).await.unwrap();
+    assert!(!env.config().await.migration_in_progress);
+
+    // Within the window the burn that crosses the threshold goes through and raises the pause
+    env.set_time(1_070).await;
+    let events = env.send_for_events(&[burn_ix(&env, &source, 51)], &[]).await;
+    assert_eq!(events, vec![AdminEvent::BurnBreakerTripped {
+        signer: admin,
+        burned_in_window: 101,
+        window_start_ts: 1_060,
+    }]);
+    assert!(env.config().await.migration_in_progress);
+    assert_custom_error(env.send(&[burn_ix(&env, &source, 1)], &[]).await, CustomError::MigrationInProgress);
+    assert_eq!(env.supply().await, 1_000 - 161);
+
+    // It holds until the config admin clears the flag
+    env.update_config(23, &[0]).await.unwrap();
+    env.send(&[burn_ix(&env, &source, 1)], &[]).await.unwrap();
+    assert_eq!(env.supply().await, 1_000 - 162);
+}
@@ -0,0 +1,147 @@
+use base64::engine::general_purpose::STANDARD;
+
+use super::*;
+use crate::{MAX_MEMO_LEN, VARINT_AMOUNT_FLAG};
+
+// Tags whose payload has a fixed width, with that width. Every handler checks its data before it reads an
+// account, so a payload one byte short or one byte long must fail without any accounts passed
+const FIXED_WIDTH_PAYLOADS: &[(u8, usize)] = &[
+    (0, 8), (1, 8), (2, 8), (3, 40), (5, 8), (6, 8), (9, 8), (10, 2), (14, 16), (15, 32), (18, 8), (20, 33),
+    (23, 10), (24, 16), (29, 32), (31, 1), (33, 1), (34, 8), (36, 64), (37, 40), (39, 17), (40, 1), (42, 32),
+    (43, 48), (44, 8), (45, 8), (50, 16), (51, 8), (52, 33), (53, 9),
+];
+
+fn assert_payload_error(result: TestResult, tag: u8, payload: &[u8]) {
+    let error = instruction_error(result);
+    assert!(
+        error == InstructionError::InvalidInstructionData
+            || error == InstructionError::Custom(CustomError::InvalidAmount as u32)
+            || error == InstructionError::Custom(CustomError::EmptyInstructionData as u32),
+        "tag {} with {} payload bytes failed with {:?}",
+        tag,
+        payload.len(),
+        error,
+    );
+}
+
+fn expiring_ix(env: &TestEnv, valid_until_slot: u64, inner: Instruction) -> Instruction {
+    let mut payload = valid_until_slot.to_le_bytes().to_vec();
+    payload.extend_from_slice(&inner.data);
+    env.ix(46, &payload, inner.accounts)
+}
+
+#[tokio::test]
+async fn empty_and_unknown_tags_are_refused() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+
+    let mut ix = env.ix(0, &[], vec![]);
+    ix.data.clear();
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::EmptyInstructionData);
+
+    let ix = env.ix(60, &[], vec![]);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn truncated_and_oversized_payloads_are_refused() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+
+    for &(tag, width) in FIXED_WIDTH_PAYLOADS {
+        let mut oversized = vec![1; width];
+        oversized.push(0xFF);
+        for payload in [&oversized[..width - 1], &oversized[..]] {
+            let ix = env.ix(tag, payload, vec![]);
+            assert_payload_error(env.send(&[ix], &[]).await, tag, payload);
+        }
+    }
+
+    // The rest take variable-width data, each with its own bounds
+    let memo = vec![1; 8 + MAX_MEMO_LEN + 1];
+    let mut expiring = u64::MAX.to_le_bytes().to_vec();
+    expiring.push(0xFF);
+    let variable_payloads: [(u8, &[u8]); 5] = [
+        (21, &[0; 34]),
+        (26, &memo[..8]),
+        (26, &memo[..]),
+        (46, &expiring[..7]),
+        (46, &expiring[..]),
+    ];
+    for (tag, payload) in variable_payloads {
+        let ix = env.ix(tag, payload, vec![]);
+        assert_payload_error(env.send(&[ix], &[]).await, tag, payload);
+    }
+
+    // UpdateConfig only learns the width of a value from its field, once the config is loaded
+    let accounts = vec![writable(env.config), signer(env.admin.pubkey())];
+    for payload in [&[0; 8][..], &[0; 10][..]] {
+        let ix = env.ix(4, payload, accounts.clone());
+        assert_payload_error(env.send(&[ix], &[]).await, 4, payload);
+    }
+}
+
+#[tokio::test]
+async fn every_payload_instruction_refuses_empty_data() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+
+    // SetFeeExempt is left out: an empty list is how the exemptions are cleared
+    let tags = FIXED_WIDTH_PAYLOADS.iter().map(|&(tag, _)| tag).filter(|&tag| tag != 42).chain([4, 21, 26, 46]);
+    for tag in tags {
+        let ix = env.ix(tag, &[], vec![]);
+        let error = instruction_error(env.send(&[ix], &[]).await);
+        assert_eq!(error, InstructionError::Custom(CustomError::EmptyInstructionData as u32), "tag {}", tag);
+    }
+}
+
+#[tokio::test]
+async fn varint_flag_is_refused_on_other_instructions() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+
+    // Only transfers and mints carry an amount that can be varint-encoded
+    let ix = env.ix(2 | VARINT_AMOUNT_FLAG, &[0x01], vec![]);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn expiring_instruction_runs_only_until_its_slot() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.ctx.warp_to_slot(100).unwrap();
+
+    let mint_ix = env.mint_ix(&destination, 10);
+    let ix = expiring_ix(&env, 99, mint_ix.clone());
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InstructionExpired);
+
+    let ix = expiring_ix(&env, 100, mint_ix);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 10);
+
+    let mut ix = env.ix(46, &[0; 7], vec![]);
+    assert_eq!(instruction_error(env.send(&[ix.clone()], &[]).await), InstructionError::InvalidInstructionData);
+    ix.data.truncate(ix.data.len() - 7);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::EmptyInstructionData);
+}
+
+#[tokio::test]
+async fn advance_epoch_is_stamped_and_logged() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.set_time(5_000).await;
+    let advance_ix = |env: &TestEnv, admin: Pubkey| env.ix(27, &[], vec![writable(env.config), signer(admin)]);
+
+    let (_, logs) = env.simulate(advance_ix(&env, env.admin.pubkey())).await;
+    let event = format!(
+        "Program log: Program data: {} {} {}",
+        STANDARD.encode(b"advance_epoch"),
+        STANDARD.encode(1u64.to_le_bytes()),
+        STANDARD.encode(5_000i64.to_le_bytes()),
+    );
+    assert!(logs.contains(&event), "{:?}", logs);
+
+    env.send(&[advance_ix(&env, env.admin.pubkey())], &[]).await.unwrap();
+    let token_config = env.config().await;
+    assert_eq!((token_config.current_epoch, token_config.epoch_started_ts), (1, 5_000));
+
+    let outsider = Keypair::new();
+    let result = env.send(&[advance_ix(&env, outsider.pubkey())], &[&outsider]).await;
+    assert_custom_error(result, CustomError::Unauthorized);
+}
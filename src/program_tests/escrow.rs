@@ -0,0 +1,151 @@
+use super::*;
+use crate::{ESCROW_SEED, VAULT_AUTHORITY_SEED};
+
+const AMOUNT: u64 = 300;
+const UNLOCK: i64 = 5_000;
+
+struct Escrow {
+    account: Pubkey,
+    recipient: Pubkey,
+    source: Pubkey,
+    vault: Pubkey,
+    authority: Pubkey,
+    destination: Pubkey,
+}
+
+// The admin escrows AMOUNT for a fresh recipient, into a vault under the escrow's own authority
+async fn prepare_escrow(env: &mut TestEnv) -> Escrow {
+    let admin = env.admin.pubkey();
+    let recipient = Pubkey::new_unique();
+    let (account, _) = Pubkey::find_program_address(
+        &[ESCROW_SEED, env.config.as_ref(), admin.as_ref(), recipient.as_ref()],
+        &env.program_id,
+    );
+    let (authority, _) = Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, account.as_ref()], &env.program_id);
+    let source = env.funded_account(&admin, AMOUNT).await;
+    let vault = env.token_account(&authority).await;
+    let destination = env.token_account(&recipient).await;
+    Escrow { account, recipient, source, vault, authority, destination }
+}
+
+async fn deposit(env: &mut TestEnv, escrow: &Escrow) -> TestResult {
+    let mut payload = AMOUNT.to_le_bytes().to_vec();
+    payload.extend_from_slice(&UNLOCK.to_le_bytes());
+    let ix = env.ix(24, &payload, vec![
+        readonly(env.config),
+        signer(env.admin.pubkey()),
+        writable(escrow.account),
+        readonly(escrow.recipient),
+        writable(escrow.source),
+        writable(escrow.vault),
+        readonly(spl_token::id()),
+        readonly(system_program::id()),
+    ]);
+    env.send(&[ix], &[]).await
+}
+
+fn release_ix(env: &TestEnv, escrow: &Escrow, token_program: Pubkey) -> Instruction {
+    env.ix(25, &[], vec![
+        readonly(env.config),
+        writable(escrow.account),
+        writable(escrow.vault),
+        readonly(escrow.authority),
+        writable(escrow.destination),
+        readonly(token_program),
+    ])
+}
+
+async fn release(env: &mut TestEnv, escrow: &Escrow) -> TestResult {
+    let ix = release_ix(env, escrow, spl_token::id());
+    env.send(&[ix], &[]).await
+}
+
+#[tokio::test]
+async fn escrow_releases_to_the_recipient_once_unlocked() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    env.set_time(UNLOCK - 1).await;
+    deposit(&mut env, &escrow).await.unwrap();
+    assert_eq!(env.balance(&escrow.vault).await, AMOUNT);
+
+    assert_custom_error(release(&mut env, &escrow).await, CustomError::TimelockNotElapsed);
+    assert_eq!(env.balance(&escrow.destination).await, 0);
+
+    env.set_time(UNLOCK).await;
+    release(&mut env, &escrow).await.unwrap();
+    assert_eq!(env.balance(&escrow.destination).await, AMOUNT);
+    assert_eq!(instruction_error(release(&mut env, &escrow).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn escrow_release_needs_the_token_program() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    deposit(&mut env, &escrow).await.unwrap();
+    env.set_time(UNLOCK).await;
+
+    let ix = release_ix(&env, &escrow, Pubkey::new_unique());
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::IncorrectProgramId);
+    assert_eq!(env.balance(&escrow.vault).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn escrow_refuses_a_vault_shared_with_the_config() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    let (shared_authority, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, env.config.as_ref()], &env.program_id);
+    let shared_vault = env.token_account(&shared_authority).await;
+
+    let escrow = Escrow { vault: shared_vault, ..escrow };
+    assert_eq!(instruction_error(deposit(&mut env, &escrow).await), InstructionError::InvalidAccountData);
+    assert_eq!(env.balance(&escrow.source).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn escrow_moves_only_the_config_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    let admin = env.admin.pubkey();
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+
+    let other_source = env.create_token_account(&other_mint, &admin).await;
+    let ix = spl_token::instruction::mint_to(&spl_token::id(), &other_mint, &other_source, &admin, &[], AMOUNT).unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    let other_vault = env.create_token_account(&other_mint, &escrow.authority).await;
+    let foreign = Escrow { source: other_source, vault: other_vault, ..escrow };
+    assert_custom_error(deposit(&mut env, &foreign).await, CustomError::MintMismatch);
+
+    deposit(&mut env, &escrow).await.unwrap();
+    env.set_time(UNLOCK).await;
+    let other_destination = env.create_token_account(&other_mint, &escrow.recipient).await;
+    let foreign = Escrow { destination: other_destination, ..escrow };
+    assert_custom_error(release(&mut env, &foreign).await, CustomError::MintMismatch);
+    assert_eq!(env.balance(&escrow.vault).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn prefunded_escrow_pda_is_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    env.prefund(&escrow.account, 1);
+
+    deposit(&mut env, &escrow).await.unwrap();
+    assert_eq!(env.account(&escrow.account).await.unwrap().owner, env.program_id);
+    assert_eq!(env.balance(&escrow.vault).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn escrows_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let escrow = prepare_escrow(&mut env).await;
+    env.update_config(23, &[1]).await.unwrap();
+    assert_custom_error(deposit(&mut env, &escrow).await, CustomError::MigrationInProgress);
+
+    env.update_config(23, &[0]).await.unwrap();
+    deposit(&mut env, &escrow).await.unwrap();
+    env.update_config(23, &[1]).await.unwrap();
+    env.set_time(UNLOCK).await;
+    assert_custom_error(release(&mut env, &escrow).await, CustomError::MigrationInProgress);
+    assert_eq!(env.balance(&escrow.vault).await, AMOUNT);
+}
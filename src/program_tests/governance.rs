@@ -0,0 +1,233 @@
+use super::*;
+use crate::{Proposal, SupplyHistory, PROPOSAL_SEED, VAULT_AUTHORITY_SEED, VOTE_ACCOUNT_SEED, VOTE_SEED};
+
+const QUORUM: u64 = 100;
+const OPENED: i64 = 1_000;
+const VOTING_PERIOD: i64 = 1_000;
+const NEW_CAP: u64 = 2 * MAX_SUPPLY;
+
+struct Vote {
+    proposal: Pubkey,
+    authority: Pubkey,
+    vault: Pubkey,
+    voter: Keypair,
+    voter_account: Pubkey,
+    history: Pubkey,
+}
+
+// Proposal 0 to double the cap, its vote vault, and a voter holding `weight` tokens
+async fn open_proposal(env: &mut TestEnv, weight: u64) -> Vote {
+    env.update_config(14, &QUORUM.to_le_bytes()).await.unwrap();
+    env.set_time(OPENED).await;
+
+    let (proposal, _) = Pubkey::find_program_address(
+        &[PROPOSAL_SEED, env.config.as_ref(), &0u64.to_le_bytes()],
+        &env.program_id,
+    );
+    let mut payload = vec![0];
+    payload.extend_from_slice(&NEW_CAP.to_le_bytes());
+    payload.extend_from_slice(&VOTING_PERIOD.to_le_bytes());
+    let ix = env.ix(39, &payload, vec![
+        writable(env.config),
+        signer(env.admin.pubkey()),
+        writable(proposal),
+        readonly(system_program::id()),
+    ]);
+    env.send(&[ix], &[]).await.unwrap();
+
+    let (authority, _) = Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, proposal.as_ref()], &env.program_id);
+    let vault = env.token_account(&authority).await;
+    let voter = env.funded_wallet().await;
+    let voter_account = env.funded_account(&voter.pubkey(), weight).await;
+    let history = env.supply_history().await;
+    Vote { proposal, authority, vault, voter, voter_account, history }
+}
+
+fn vote_record(env: &TestEnv, vote: &Vote) -> Pubkey {
+    let (record, _) = Pubkey::find_program_address(
+        &[VOTE_SEED, vote.proposal.as_ref(), vote.voter.pubkey().as_ref()],
+        &env.program_id,
+    );
+    record
+}
+
+async fn cast_vote(env: &mut TestEnv, vote: &Vote, support: bool) -> TestResult {
+    let (account_record, _) = Pubkey::find_program_address(
+        &[VOTE_ACCOUNT_SEED, vote.proposal.as_ref(), vote.voter_account.as_ref()],
+        &env.program_id,
+    );
+    let ix = env.ix(40, &[support as u8], vec![
+        readonly(env.config),
+        writable(vote.proposal),
+        signer(vote.voter.pubkey()),
+        writable(vote.voter_account),
+        writable(vote_record(env, vote)),
+        writable(account_record),
+        writable(vote.vault),
+        readonly(spl_token::id()),
+        readonly(system_program::id()),
+    ]);
+    env.send(&[ix], &[&vote.voter]).await
+}
+
+async fn reclaim_vote(env: &mut TestEnv, vote: &Vote) -> TestResult {
+    let ix = env.ix(55, &[], vec![
+        readonly(env.config),
+        readonly(vote.proposal),
+        signer(vote.voter.pubkey()),
+        writable(vote_record(env, vote)),
+        writable(vote.vault),
+        readonly(vote.authority),
+        writable(vote.voter_account),
+        readonly(spl_token::id()),
+    ]);
+    env.send(&[ix], &[&vote.voter]).await
+}
+
+async fn proposal(env: &mut TestEnv, address: &Pubkey) -> Proposal {
+    let account = env.account(address).await.expect("proposal exists");
+    Proposal::deserialize(&mut &account.data[..]).unwrap()
+}
+
+#[tokio::test]
+async fn vote_locks_the_voting_tokens_for_the_proposal() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, 150).await;
+
+    cast_vote(&mut env, &vote, true).await.unwrap();
+    assert_eq!(proposal(&mut env, &vote.proposal).await.yes_votes, 150);
+    // Nothing is left to hand to another wallet for a second vote
+    assert_eq!(env.balance(&vote.voter_account).await, 0);
+    assert_eq!(env.balance(&vote.vault).await, 150);
+
+    env.mint_to(&vote.voter_account, 10).await.unwrap();
+    let result = cast_vote(&mut env, &vote, true).await;
+    assert_eq!(instruction_error(result), InstructionError::AccountAlreadyInitialized);
+    assert_eq!(proposal(&mut env, &vote.proposal).await.yes_votes, 150);
+}
+
+#[tokio::test]
+async fn locked_votes_come_back_once_voting_closes() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, 150).await;
+    cast_vote(&mut env, &vote, true).await.unwrap();
+
+    assert_custom_error(reclaim_vote(&mut env, &vote).await, CustomError::TimelockNotElapsed);
+
+    env.set_time(OPENED + VOTING_PERIOD).await;
+    env.send(&[execute_ix(&env, &vote)], &[]).await.unwrap();
+    assert_eq!(env.config().await.max_supply, NEW_CAP);
+
+    reclaim_vote(&mut env, &vote).await.unwrap();
+    assert_eq!(env.balance(&vote.voter_account).await, 150);
+    assert_eq!(instruction_error(reclaim_vote(&mut env, &vote).await), InstructionError::InvalidAccountData);
+    assert_eq!(env.balance(&vote.vault).await, 0);
+}
+
+fn execute_ix(env: &TestEnv, vote: &Vote) -> Instruction {
+    env.ix(41, &[], vec![
+        writable(env.config),
+        writable(vote.proposal),
+        readonly(env.mint),
+        writable(vote.history),
+    ])
+}
+
+#[tokio::test]
+async fn proposal_executes_once_after_voting_closes() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, QUORUM).await;
+    cast_vote(&mut env, &vote, true).await.unwrap();
+
+    let result = env.send(&[execute_ix(&env, &vote)], &[]).await;
+    assert_custom_error(result, CustomError::TimelockNotElapsed);
+
+    env.set_time(OPENED + VOTING_PERIOD).await;
+    let voter = env.funded_wallet().await;
+    let voter_account = env.funded_account(&voter.pubkey(), 10).await;
+    let late = Vote { voter, voter_account, ..vote };
+    assert_custom_error(cast_vote(&mut env, &late, false).await, CustomError::VotingClosed);
+
+    env.send(&[execute_ix(&env, &late)], &[]).await.unwrap();
+    assert_eq!(env.config().await.max_supply, NEW_CAP);
+    let history = env.account(&late.history).await.unwrap();
+    let recorded = SupplyHistory::try_from_slice(&history.data).unwrap().recent();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!((recorded[0].old_max_supply, recorded[0].new_max_supply), (MAX_SUPPLY, NEW_CAP));
+    assert_eq!(recorded[0].signer, late.proposal);
+    assert!(proposal(&mut env, &late.proposal).await.executed);
+    let result = env.send(&[execute_ix(&env, &late)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn proposal_needs_quorum_and_a_majority() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, QUORUM - 1).await;
+    cast_vote(&mut env, &vote, true).await.unwrap();
+    env.set_time(OPENED + VOTING_PERIOD).await;
+
+    let result = env.send(&[execute_ix(&env, &vote)], &[]).await;
+    assert_custom_error(result, CustomError::ProposalNotPassed);
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn proposals_need_governance_enabled_and_a_valid_payload() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let (proposal, _) =
+        Pubkey::find_program_address(&[PROPOSAL_SEED, env.config.as_ref(), &0u64.to_le_bytes()], &env.program_id);
+    let create_ix = |env: &TestEnv, param: u8, voting_period: i64| {
+        let mut payload = vec![param];
+        payload.extend_from_slice(&NEW_CAP.to_le_bytes());
+        payload.extend_from_slice(&voting_period.to_le_bytes());
+        env.ix(39, &payload, vec![
+            writable(env.config),
+            signer(env.admin.pubkey()),
+            writable(proposal),
+            readonly(system_program::id()),
+        ])
+    };
+
+    assert_custom_error(env.send(&[create_ix(&env, 0, VOTING_PERIOD)], &[]).await, CustomError::Unauthorized);
+
+    env.update_config(14, &QUORUM.to_le_bytes()).await.unwrap();
+    let result = env.send(&[create_ix(&env, 1, VOTING_PERIOD)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+    let result = env.send(&[create_ix(&env, 0, 0)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+    assert!(env.account(&proposal).await.is_none());
+}
+
+#[tokio::test]
+async fn prefunded_vote_records_are_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, 150).await;
+    let (account_record, _) = Pubkey::find_program_address(
+        &[VOTE_ACCOUNT_SEED, vote.proposal.as_ref(), vote.voter_account.as_ref()],
+        &env.program_id,
+    );
+    let record = vote_record(&env, &vote);
+    env.prefund(&record, 1);
+    env.prefund(&account_record, 1);
+
+    cast_vote(&mut env, &vote, true).await.unwrap();
+    assert_eq!(proposal(&mut env, &vote.proposal).await.yes_votes, 150);
+    assert_eq!(env.account(&record).await.unwrap().owner, env.program_id);
+}
+
+#[tokio::test]
+async fn votes_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vote = open_proposal(&mut env, QUORUM).await;
+    env.update_config(23, &[1]).await.unwrap();
+    assert_custom_error(cast_vote(&mut env, &vote, true).await, CustomError::MigrationInProgress);
+
+    env.update_config(23, &[0]).await.unwrap();
+    cast_vote(&mut env, &vote, true).await.unwrap();
+    env.set_time(OPENED + VOTING_PERIOD).await;
+    env.update_config(23, &[1]).await.unwrap();
+    assert_custom_error(env.send(&[execute_ix(&env, &vote)], &[]).await, CustomError::MigrationInProgress);
+    assert_custom_error(reclaim_vote(&mut env, &vote).await, CustomError::MigrationInProgress);
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY);
+}
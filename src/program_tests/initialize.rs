@@ -0,0 +1,189 @@
+use super::*;
+use crate::CONFIG_LEN;
+
+#[tokio::test]
+async fn initialize_records_the_mint_and_admin() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let config = env.config().await;
+    assert!(config.initialized);
+    assert_eq!(config.admin_pubkey, env.admin.pubkey());
+    assert_eq!(config.mint, env.mint);
+    assert_eq!(config.decimals, DECIMALS);
+}
+
+#[tokio::test]
+async fn only_the_mint_authority_can_initialize() {
+    let mut env = TestEnv::with_mint().await;
+    let attacker = env.funded_wallet().await;
+
+    // Paying for the config and naming itself admin would otherwise hand the attacker the token
+    let mut payload = attacker.pubkey().to_bytes().to_vec();
+    payload.extend_from_slice(&MAX_SUPPLY.to_le_bytes());
+    let ix = env.ix(3, &payload, vec![
+        writable(env.config),
+        readonly(env.mint),
+        signer(attacker.pubkey()),
+        readonly(system_program::id()),
+    ]);
+    assert_custom_error(env.send(&[ix], &[&attacker]).await, CustomError::UnauthorizedMint);
+    let config = env.config;
+    assert!(env.account(&config).await.is_none());
+
+    env.initialize(MAX_SUPPLY).await.unwrap();
+    assert_eq!(env.config().await.admin_pubkey, env.admin.pubkey());
+}
+
+#[tokio::test]
+async fn initialize_and_mint_launches_in_one_instruction() {
+    let mut env = TestEnv::with_mint().await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    let mut payload = admin.to_bytes().to_vec();
+    payload.extend_from_slice(&MAX_SUPPLY.to_le_bytes());
+    payload.extend_from_slice(&500u64.to_le_bytes());
+    let ix = env.ix(43, &payload, vec![
+        writable(env.config),
+        writable(env.mint),
+        signer(admin),
+        writable(destination),
+        readonly(spl_token::id()),
+        signer(admin),
+        readonly(system_program::id()),
+    ]);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.config().await.admin_pubkey, admin);
+    assert_eq!(env.balance(&destination).await, 500);
+}
+
+#[tokio::test]
+async fn config_size_is_returned_for_clients() {
+    let mut env = TestEnv::with_mint().await;
+    let ix = env.ix(48, &[], vec![]);
+    let (data, _) = env.simulate(ix).await;
+    assert_eq!(data, (CONFIG_LEN as u64).to_le_bytes());
+}
+
+fn initialize_ix(env: &TestEnv, tag: u8, max_supply: u64) -> Instruction {
+    let mut payload = env.admin.pubkey().to_bytes().to_vec();
+    payload.extend_from_slice(&max_supply.to_le_bytes());
+    env.ix(tag, &payload, vec![
+        writable(env.config),
+        readonly(env.mint),
+        signer(env.admin.pubkey()),
+        readonly(system_program::id()),
+    ])
+}
+
+// Puts a program-owned account at the config PDA, as if a client had allocated it before initializing
+async fn preallocate_config(env: &mut TestEnv, data: Vec<u8>, lamports: u64) {
+    let account = SdkAccount { lamports, data, owner: env.program_id, executable: false, rent_epoch: 0 };
+    let config = env.config;
+    env.ctx.set_account(&config, &account.into());
+}
+
+#[tokio::test]
+async fn initialize_runs_once_unless_idempotent_with_the_same_parameters() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+
+    let ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::AccountAlreadyInitialized);
+
+    env.update_config(6, &250u64.to_le_bytes()).await.unwrap();
+    let ix = initialize_ix(&env, 37, MAX_SUPPLY);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.config().await.max_transfer_amount, 250);
+
+    let ix = initialize_ix(&env, 37, MAX_SUPPLY + 1);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::AccountAlreadyInitialized);
+}
+
+#[tokio::test]
+async fn idempotent_initialize_creates_a_missing_config() {
+    let mut env = TestEnv::with_mint().await;
+    let ix = initialize_ix(&env, 37, MAX_SUPPLY);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.config().await.max_supply, MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn initialize_checks_the_mint_and_config_addresses() {
+    let mut env = TestEnv::with_mint().await;
+
+    let mut ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    ix.accounts[0] = writable(Pubkey::new_unique());
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidSeeds);
+
+    let mut ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    ix.accounts[1] = readonly(env.admin.pubkey());
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::IncorrectProgramId);
+}
+
+#[tokio::test]
+async fn initialize_refuses_a_truncated_payload() {
+    let mut env = TestEnv::with_mint().await;
+
+    let mut ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    ix.data.pop();
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn preallocated_config_must_be_rent_exempt() {
+    let mut env = TestEnv::with_mint().await;
+
+    preallocate_config(&mut env, vec![0; CONFIG_LEN], 1).await;
+    let ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::AccountNotRentExempt);
+}
+
+#[tokio::test]
+async fn preallocated_config_must_be_all_zero() {
+    let mut env = TestEnv::with_mint().await;
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+
+    let mut stale = vec![0; CONFIG_LEN];
+    stale[CONFIG_LEN - 1] = 7;
+    preallocate_config(&mut env, stale, rent.minimum_balance(CONFIG_LEN)).await;
+    let ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn preallocated_config_may_be_larger_than_needed() {
+    let mut env = TestEnv::with_mint().await;
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    let len = CONFIG_LEN + 16;
+
+    preallocate_config(&mut env, vec![0; CONFIG_LEN - 1], rent.minimum_balance(CONFIG_LEN)).await;
+    let ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidAccountData);
+
+    // An oversized clean account is used as is, with everything past the struct left zeroed
+    preallocate_config(&mut env, vec![0; len], rent.minimum_balance(len)).await;
+    let ix = initialize_ix(&env, 3, MAX_SUPPLY);
+    env.send(&[ix], &[]).await.unwrap();
+    let config = env.config;
+    let data = env.account(&config).await.unwrap().data;
+    assert_eq!(data.len(), len);
+    assert!(data[CONFIG_LEN..].iter().all(|byte| *byte == 0));
+    assert!(env.config().await.initialized);
+}
+
+#[tokio::test]
+async fn prefunded_config_pda_is_still_created() {
+    // Short of rent the payer tops it up, past rent it keeps the surplus
+    for lamports in [1, 10_000_000_000] {
+        let mut env = TestEnv::with_mint().await;
+        let config = env.config;
+        env.prefund(&config, lamports);
+
+        env.initialize(MAX_SUPPLY).await.unwrap();
+        let account = env.account(&config).await.unwrap();
+        assert_eq!(account.owner, env.program_id);
+        assert_eq!(account.data.len(), CONFIG_LEN);
+        let rent = env.ctx.banks_client.get_rent().await.unwrap();
+        assert_eq!(account.lamports, rent.minimum_balance(CONFIG_LEN).max(lamports));
+        assert!(env.config().await.initialized);
+    }
+}
@@ -0,0 +1,154 @@
+use super::*;
+use crate::MIGRATION_SEED;
+
+struct Migration {
+    record: Pubkey,
+    new_mint: Pubkey,
+    new_config: Pubkey,
+    source: Pubkey,
+    destination: Pubkey,
+}
+
+// Opens a 2:1 migration out of the config's mint into new_mint, recorded at record
+fn initialize_migration_ix(env: &TestEnv, record: Pubkey, new_mint: Pubkey) -> Instruction {
+    let mut payload = 2u64.to_le_bytes().to_vec();
+    payload.extend_from_slice(&1u64.to_le_bytes());
+    env.ix(50, &payload, vec![
+        readonly(env.config),
+        signer(env.admin.pubkey()),
+        writable(record),
+        readonly(env.mint),
+        readonly(new_mint),
+        readonly(system_program::id()),
+    ])
+}
+
+// An open 2:1 migration out of the config's mint, with 100 old tokens in the admin's source account. The new
+// mint gets its own config before its mint authority is handed to the migration record
+async fn open_migration(env: &mut TestEnv) -> Migration {
+    let admin = env.admin.pubkey();
+    let new_mint = env.create_mint(&admin, DECIMALS).await;
+    let (record, _) =
+        Pubkey::find_program_address(&[MIGRATION_SEED, env.mint.as_ref(), new_mint.as_ref()], &env.program_id);
+    let new_config = config_address(&env.program_id, &new_mint).0;
+    let mut payload = admin.to_bytes().to_vec();
+    payload.extend_from_slice(&MAX_SUPPLY.to_le_bytes());
+    let instructions = [
+        env.ix(3, &payload, vec![
+            writable(new_config),
+            readonly(new_mint),
+            signer(admin),
+            readonly(system_program::id()),
+        ]),
+        spl_token::instruction::set_authority(
+            &spl_token::id(),
+            &new_mint,
+            Some(&record),
+            spl_token::instruction::AuthorityType::MintTokens,
+            &admin,
+            &[],
+        ).unwrap(),
+        initialize_migration_ix(env, record, new_mint),
+    ];
+    env.send(&instructions, &[]).await.unwrap();
+
+    let source = env.token_account(&admin).await;
+    env.mint_to(&source, 100).await.unwrap();
+    let destination = env.create_token_account(&new_mint, &admin).await;
+    Migration { record, new_mint, new_config, source, destination }
+}
+
+fn migrate_ix(env: &TestEnv, migration: &Migration, new_mint: Pubkey, token_program: Pubkey, amount: u64) -> Instruction {
+    env.ix(51, &amount.to_le_bytes(), vec![
+        readonly(migration.record),
+        signer(env.admin.pubkey()),
+        writable(migration.source),
+        writable(env.mint),
+        writable(migration.destination),
+        writable(new_mint),
+        readonly(token_program),
+        writable(env.config),
+        writable(migration.new_config),
+    ])
+}
+
+#[tokio::test]
+async fn migration_burns_old_tokens_and_mints_at_the_ratio() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let migration = open_migration(&mut env).await;
+
+    let ix = migrate_ix(&env, &migration, migration.new_mint, spl_token::id(), 30);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&migration.source).await, 70);
+    assert_eq!(env.balance(&migration.destination).await, 60);
+    assert_eq!(env.supply().await, 70);
+
+    // Each config counts its own side of the swap
+    assert_eq!(env.config().await.total_burned, 30);
+    let account = env.account(&migration.new_config).await.unwrap();
+    assert_eq!(TokenConfig::deserialize(&mut &account.data[..]).unwrap().total_minted, 60);
+}
+
+#[tokio::test]
+async fn migration_waits_for_a_config_migration_on_either_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let migration = open_migration(&mut env).await;
+    let flag_ix = |env: &TestEnv, config: Pubkey, value: u8| {
+        env.ix(4, &[23, value], vec![writable(config), signer(env.admin.pubkey())])
+    };
+
+    for config in [env.config, migration.new_config] {
+        env.send(&[flag_ix(&env, config, 1)], &[]).await.unwrap();
+        let ix = migrate_ix(&env, &migration, migration.new_mint, spl_token::id(), 30);
+        assert_custom_error(env.send(&[ix], &[]).await, CustomError::MigrationInProgress);
+        env.send(&[flag_ix(&env, config, 0)], &[]).await.unwrap();
+    }
+    assert_eq!(env.balance(&migration.source).await, 100);
+
+    let ix = migrate_ix(&env, &migration, migration.new_mint, spl_token::id(), 30);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&migration.destination).await, 60);
+}
+
+#[tokio::test]
+async fn migration_refuses_a_substitute_token_program() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let migration = open_migration(&mut env).await;
+
+    // The program would otherwise hand its mint-authority signature to whatever program it was given
+    let ix = migrate_ix(&env, &migration, migration.new_mint, Pubkey::new_unique(), 30);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::IncorrectProgramId);
+    assert_eq!(env.balance(&migration.source).await, 100);
+}
+
+#[tokio::test]
+async fn migration_refuses_mints_outside_the_record() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let migration = open_migration(&mut env).await;
+    let admin = env.admin.pubkey();
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+
+    let ix = migrate_ix(&env, &migration, other_mint, spl_token::id(), 30);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MintMismatch);
+    assert_eq!(env.balance(&migration.source).await, 100);
+}
+
+#[tokio::test]
+async fn opening_a_migration_is_logged() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let new_mint_keypair = Keypair::new();
+    let new_mint = new_mint_keypair.pubkey();
+    let (record, _) =
+        Pubkey::find_program_address(&[MIGRATION_SEED, env.mint.as_ref(), new_mint.as_ref()], &env.program_id);
+    env.create_mint_at(&new_mint_keypair, &record, DECIMALS).await;
+
+    let ix = initialize_migration_ix(&env, record, new_mint);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::MigrationInitialized {
+        signer: env.admin.pubkey(),
+        old_mint: env.mint,
+        new_mint,
+        ratio_num: 2,
+        ratio_den: 1,
+    }]);
+}
@@ -0,0 +1,555 @@
+use solana_program::{program_error::ProgramError, program_option::COption};
+
+use super::*;
+use crate::{MintReceipt, OwnerAccounts, MINT_RECEIPT_SEED, OWNER_ACCOUNTS_SEED, MINT_WINDOW_SECONDS, SECONDS_PER_YEAR, UNLIMITED_SUPPLY, VARINT_AMOUNT_FLAG};
+
+#[tokio::test]
+async fn mint_operations_are_capped() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.update_config(0, &2u64.to_le_bytes()).await.unwrap();
+
+    env.mint_to(&destination, 10).await.unwrap();
+    env.mint_to(&destination, 10).await.unwrap();
+    assert_eq!(env.config().await.mint_count, 2);
+
+    // At the cap every further mint is refused, whatever the amount
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::MintOperationsExceeded);
+    assert_eq!(env.supply().await, 20);
+    assert_eq!(env.config().await.mint_count, 2);
+}
+
+#[tokio::test]
+async fn zero_operation_cap_leaves_minting_unbounded() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    for _ in 0..3 {
+        env.mint_to(&destination, 5).await.unwrap();
+    }
+    assert_eq!(env.config().await.mint_count, 3);
+    assert_eq!(env.balance(&destination).await, 15);
+}
+
+#[tokio::test]
+async fn mint_count_overflow_is_an_error() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let token_config = TokenConfig { mint_count: u64::MAX, ..env.config().await };
+    env.write_config(&token_config).await;
+
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::ArithmeticOverflow);
+    assert_eq!(env.supply().await, 0);
+}
+
+fn batch_mint_ix(env: &TestEnv, legs: &[(Pubkey, u64)], extra_accounts: Vec<AccountMeta>) -> Instruction {
+    let payload: Vec<u8> = legs.iter().flat_map(|(_, amount)| amount.to_le_bytes()).collect();
+    let mut accounts = vec![
+        writable(env.mint),
+        signer(env.admin.pubkey()),
+        readonly(spl_token::id()),
+        writable(env.config),
+    ];
+    accounts.extend(legs.iter().map(|(destination, _)| writable(*destination)));
+    accounts.extend(extra_accounts);
+    env.ix(5, &payload, accounts)
+}
+
+#[tokio::test]
+async fn batch_mint_over_the_threshold_needs_the_cosigner() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let cosigner = Keypair::new();
+    env.update_config(18, &100u64.to_le_bytes()).await.unwrap();
+    env.update_config(19, cosigner.pubkey().as_ref()).await.unwrap();
+    let admin = env.admin.pubkey();
+    let first = env.token_account(&admin).await;
+    let second = env.token_account(&admin).await;
+
+    // Each leg is under the threshold, but together they are a high-value mint
+    let ix = batch_mint_ix(&env, &[(first, 60), (second, 60)], vec![]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::UnauthorizedMint);
+    assert_eq!(env.supply().await, 0);
+
+    let ix = batch_mint_ix(&env, &[(first, 60), (second, 60)], vec![signer(cosigner.pubkey())]);
+    env.send(&[ix], &[&cosigner]).await.unwrap();
+    assert_eq!(env.supply().await, 120);
+}
+
+fn receipt_address(env: &TestEnv, destination: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[MINT_RECEIPT_SEED, env.config.as_ref(), destination.as_ref()], &env.program_id).0
+}
+
+#[tokio::test]
+async fn batch_mint_counts_each_leg_against_the_per_account_cap() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.update_config(20, &100u64.to_le_bytes()).await.unwrap();
+    let admin = env.admin.pubkey();
+    let first = env.token_account(&admin).await;
+    let second = env.token_account(&admin).await;
+    let receipts = |env: &TestEnv, destinations: &[Pubkey]| {
+        let mut accounts: Vec<_> = destinations.iter().map(|d| writable(receipt_address(env, d))).collect();
+        accounts.push(readonly(system_program::id()));
+        accounts
+    };
+
+    // Two legs to the same account add up past its cap even though each fits on its own
+    let extra = receipts(&env, &[first, first]);
+    let ix = batch_mint_ix(&env, &[(first, 60), (first, 60)], extra);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::PerAccountCapExceeded);
+
+    let extra = receipts(&env, &[first, second]);
+    let ix = batch_mint_ix(&env, &[(first, 60), (second, 60)], extra);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&first).await, 60);
+    assert_eq!(env.balance(&second).await, 60);
+
+    // The receipts from the batch carry over to single mints
+    let mut ix = env.mint_ix(&first, 50);
+    ix.accounts.extend(receipts(&env, &[first]));
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::PerAccountCapExceeded);
+}
+
+#[tokio::test]
+async fn batch_mint_checks_every_destination() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let good = env.token_account(&admin).await;
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    let foreign = env.create_token_account(&other_mint, &admin).await;
+
+    let ix = batch_mint_ix(&env, &[(good, 10), (foreign, 10)], vec![]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MintMismatch);
+
+    let frozen = env.token_account(&admin).await;
+    let freeze_ix = spl_token::instruction::freeze_account(&spl_token::id(), &frozen, &env.mint, &admin, &[]).unwrap();
+    env.send(&[freeze_ix], &[]).await.unwrap();
+    let ix = batch_mint_ix(&env, &[(good, 10), (frozen, 10)], vec![]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::AccountFrozen);
+    assert_eq!(env.supply().await, 0);
+}
+
+#[tokio::test]
+async fn mint_stops_at_the_cap_and_on_supply_overflow() {
+    let mut env = TestEnv::new(100).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    env.mint_to(&destination, 100).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::MaxSupplyExceeded);
+
+    // Uncapped, the only limit left is the u64 supply itself
+    let token_config = TokenConfig { max_supply: UNLIMITED_SUPPLY, ..env.config().await };
+    env.write_config(&token_config).await;
+    env.mint_to(&destination, u64::MAX - 100).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::ArithmeticOverflow);
+    assert_eq!(env.supply().await, u64::MAX);
+}
+
+#[tokio::test]
+async fn only_the_mint_admin_can_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let outsider = Keypair::new();
+
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts[2] = signer(outsider.pubkey());
+    assert_custom_error(env.send(&[ix], &[&outsider]).await, CustomError::UnauthorizedMint);
+    assert_eq!(env.supply().await, 0);
+}
+
+#[tokio::test]
+async fn mint_authority_must_match_the_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    let ix = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &env.mint,
+        Some(&Pubkey::new_unique()),
+        spl_token::instruction::AuthorityType::MintTokens,
+        &admin,
+        &[],
+    )
+    .unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::UnauthorizedMint);
+}
+
+#[tokio::test]
+async fn removed_mint_authority_finalizes_the_supply() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    let ix = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &env.mint,
+        None,
+        spl_token::instruction::AuthorityType::MintTokens,
+        &admin,
+        &[],
+    )
+    .unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::MintingFinalized);
+}
+
+#[tokio::test]
+async fn mint_below_the_minimum_amount_is_refused() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    env.update_config(5, &10u64.to_le_bytes()).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 9).await, CustomError::InvalidAmount);
+    env.mint_to(&destination, 10).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 10);
+}
+
+#[tokio::test]
+async fn mint_decimals_must_match_the_config() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    let token_config = TokenConfig { decimals: DECIMALS + 1, ..env.config().await };
+    env.write_config(&token_config).await;
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::DecimalsMismatch);
+}
+
+#[tokio::test]
+async fn mint_refuses_a_drifted_freeze_authority() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    env.update_config(4, admin.as_ref()).await.unwrap();
+    env.mint_to(&destination, 10).await.unwrap();
+
+    env.update_config(4, Pubkey::new_unique().as_ref()).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::FreezeAuthorityMismatch);
+}
+
+#[tokio::test]
+async fn mint_rewards_go_to_the_rewards_vault() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let vault = env.token_account(&admin).await;
+    env.update_config(1, &1_000u16.to_le_bytes()).await.unwrap();
+    env.update_config(2, vault.as_ref()).await.unwrap();
+
+    let mut ix = env.mint_ix(&destination, 100);
+    ix.accounts.push(writable(vault));
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 90);
+    assert_eq!(env.balance(&vault).await, 10);
+
+    let mut ix = env.mint_ix(&destination, 100);
+    ix.accounts.push(writable(destination));
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn mint_creates_a_missing_associated_account() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let wallet = Pubkey::new_unique();
+    let ata = spl_associated_token_account::get_associated_token_address(&wallet, &env.mint);
+    let creation_accounts = |ata_program: Pubkey| {
+        vec![
+            signer(admin),
+            readonly(wallet),
+            readonly(system_program::id()),
+            readonly(ata_program),
+        ]
+    };
+
+    let mut ix = env.mint_ix(&ata, 10);
+    ix.accounts.extend(creation_accounts(Pubkey::new_unique()));
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::IncorrectProgramId);
+
+    let mut ix = env.mint_ix(&ata, 10);
+    ix.accounts.extend(creation_accounts(spl_associated_token_account::id()));
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&ata).await, 10);
+}
+
+#[tokio::test]
+async fn mint_needs_the_admin_signer_threshold() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let first = Keypair::new();
+    let second = Keypair::new();
+
+    let mut payload = vec![2];
+    payload.extend_from_slice(first.pubkey().as_ref());
+    payload.extend_from_slice(second.pubkey().as_ref());
+    let ix = env.ix(20, &payload, vec![writable(env.config), signer(admin)]);
+    env.send(&[ix], &[]).await.unwrap();
+
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts.push(signer(first.pubkey()));
+    assert_custom_error(env.send(&[ix], &[&first]).await, CustomError::UnauthorizedMint);
+
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts.extend([signer(first.pubkey()), signer(second.pubkey())]);
+    env.send(&[ix], &[&first, &second]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 10);
+}
+
+#[tokio::test]
+async fn mint_over_the_threshold_needs_the_cosigner() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let cosigner = Keypair::new();
+    env.update_config(18, &100u64.to_le_bytes()).await.unwrap();
+    env.update_config(19, cosigner.pubkey().as_ref()).await.unwrap();
+
+    env.mint_to(&destination, 100).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 101).await, CustomError::UnauthorizedMint);
+
+    let mut ix = env.mint_ix(&destination, 101);
+    ix.accounts.push(signer(cosigner.pubkey()));
+    env.send(&[ix], &[&cosigner]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 201);
+}
+
+#[tokio::test]
+async fn daily_limit_resets_with_the_window() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.set_time(1_000).await;
+    env.update_config(11, &100u64.to_le_bytes()).await.unwrap();
+
+    env.mint_to(&destination, 60).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 50).await, CustomError::DailyMintLimitExceeded);
+    env.mint_to(&destination, 40).await.unwrap();
+
+    env.set_time(1_000 + MINT_WINDOW_SECONDS).await;
+    env.mint_to(&destination, 100).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 200);
+}
+
+#[tokio::test]
+async fn post_cap_inflation_accrues_from_when_the_cap_was_hit() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.set_time(1_000).await;
+    env.update_config(17, &1_000u16.to_le_bytes()).await.unwrap();
+
+    env.mint_to(&destination, MAX_SUPPLY).await.unwrap();
+    assert_eq!(env.config().await.cap_reached_ts, 1_000);
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::MaxSupplyExceeded);
+
+    // 10% a year of a 1_000_000 cap is 50_000 after half a year
+    env.set_time(1_000 + SECONDS_PER_YEAR / 2).await;
+    env.mint_to(&destination, 50_000).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::MaxSupplyExceeded);
+    assert_eq!(env.supply().await, MAX_SUPPLY + 50_000);
+}
+
+#[tokio::test]
+async fn single_mint_refuses_a_frozen_destination() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let freeze_ix = spl_token::instruction::freeze_account(&spl_token::id(), &destination, &env.mint, &admin, &[])
+        .unwrap();
+    env.send(&[freeze_ix], &[]).await.unwrap();
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::AccountFrozen);
+}
+
+#[tokio::test]
+async fn single_mints_count_against_the_per_account_cap() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    env.update_config(20, &100u64.to_le_bytes()).await.unwrap();
+    let destination = env.token_account(&admin).await;
+    let receipt = [writable(receipt_address(&env, &destination)), readonly(system_program::id())];
+    let mut ix = env.mint_ix(&destination, 100);
+    ix.accounts.extend(receipt.clone());
+    env.send(&[ix], &[]).await.unwrap();
+    let mut ix = env.mint_ix(&destination, 1);
+    ix.accounts.extend(receipt);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::PerAccountCapExceeded);
+}
+
+#[tokio::test]
+async fn mint_accepts_a_varint_amount() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+
+    // 300 is 0b10_0101100, sent low group first
+    let mut ix = env.mint_ix(&destination, 0);
+    ix.data = instruction_data(1 | VARINT_AMOUNT_FLAG, &[0xac, 0x02]);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&destination).await, 300);
+
+    // A padded encoding of the same amount is refused
+    let mut ix = env.mint_ix(&destination, 0);
+    ix.data = instruction_data(1 | VARINT_AMOUNT_FLAG, &[0xac, 0x82, 0x00]);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InvalidAmount);
+}
+
+#[tokio::test]
+async fn simulate_mint_reports_the_outcome_without_minting() {
+    let mut env = TestEnv::new(100).await;
+    let simulate_ix = |env: &TestEnv, amount: u64| {
+        env.ix(44, &amount.to_le_bytes(), vec![
+            readonly(env.mint),
+            signer(env.admin.pubkey()),
+            readonly(env.config),
+        ])
+    };
+
+    let (data, _) = env.simulate(simulate_ix(&env, 100)).await;
+    assert_eq!(data, 0u64.to_le_bytes());
+    let (data, _) = env.simulate(simulate_ix(&env, 101)).await;
+    assert_eq!(data, u64::from(ProgramError::from(CustomError::MaxSupplyExceeded)).to_le_bytes());
+    assert_eq!(env.supply().await, 0);
+}
+
+#[tokio::test]
+async fn prefunded_receipt_pda_is_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    env.update_config(20, &100u64.to_le_bytes()).await.unwrap();
+    let destination = env.token_account(&admin).await;
+    let receipt = receipt_address(&env, &destination);
+    env.prefund(&receipt, 1);
+
+    let mut ix = env.mint_ix(&destination, 60);
+    ix.accounts.extend([writable(receipt), readonly(system_program::id())]);
+    env.send(&[ix], &[]).await.unwrap();
+    let account = env.account(&receipt).await.unwrap();
+    assert_eq!(account.owner, env.program_id);
+    assert_eq!(MintReceipt::try_from_slice(&account.data).unwrap().received, 60);
+}
+
+fn owner_record_address(env: &TestEnv, owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[OWNER_ACCOUNTS_SEED, env.config.as_ref(), owner.as_ref()], &env.program_id).0
+}
+
+#[tokio::test]
+async fn mints_reach_only_as_many_accounts_per_owner_as_the_limit() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.update_config(26, &1u64.to_le_bytes()).await.unwrap();
+    let owner = Pubkey::new_unique();
+    let first = env.token_account(&owner).await;
+    let second = env.token_account(&owner).await;
+    let record = owner_record_address(&env, &owner);
+    let accounts = |env: &TestEnv, destination: &Pubkey| {
+        [writable(receipt_address(env, destination)), readonly(system_program::id()), writable(record)]
+    };
+
+    let mut ix = env.mint_ix(&first, 10);
+    ix.accounts.extend(accounts(&env, &first));
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(OwnerAccounts::try_from_slice(&env.account(&record).await.unwrap().data).unwrap().count, 1);
+
+    // An account already counted takes further mints without the owner record
+    let mut ix = env.mint_ix(&first, 10);
+    ix.accounts.extend([writable(receipt_address(&env, &first)), readonly(system_program::id())]);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&first).await, 20);
+
+    let mut ix = env.mint_ix(&second, 10);
+    ix.accounts.extend(accounts(&env, &second));
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::OwnerAccountLimitExceeded);
+    assert_eq!(env.balance(&second).await, 0);
+
+    let mut ix = env.mint_ix(&second, 10);
+    ix.accounts.extend([writable(receipt_address(&env, &second)), readonly(system_program::id())]);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::NotEnoughAccountKeys);
+}
+
+#[tokio::test]
+async fn batch_mint_counts_each_new_account_against_its_owner() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.update_config(26, &2u64.to_le_bytes()).await.unwrap();
+    let owner = Pubkey::new_unique();
+    let mut destinations = vec![];
+    for _ in 0..3 {
+        destinations.push(env.token_account(&owner).await);
+    }
+    let extra = |env: &TestEnv, destinations: &[Pubkey]| {
+        let mut accounts: Vec<_> = destinations.iter().map(|d| writable(receipt_address(env, d))).collect();
+        accounts.push(readonly(system_program::id()));
+        accounts.extend(destinations.iter().map(|_| writable(owner_record_address(env, &owner))));
+        accounts
+    };
+
+    let legs: Vec<_> = destinations.iter().map(|d| (*d, 10)).collect();
+    let ix = batch_mint_ix(&env, &legs, extra(&env, &destinations));
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::OwnerAccountLimitExceeded);
+
+    // Two legs to one account count it once
+    let legs = [destinations[0], destinations[0], destinations[1]];
+    let ix = batch_mint_ix(&env, &[(legs[0], 10), (legs[1], 10), (legs[2], 10)], extra(&env, &legs));
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(env.balance(&destinations[0]).await, 20);
+    let record = env.account(&owner_record_address(&env, &owner)).await.unwrap();
+    assert_eq!(OwnerAccounts::try_from_slice(&record.data).unwrap().count, 2);
+}
+
+#[tokio::test]
+async fn disabling_minting_freezes_the_supply_for_good() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    env.mint_to(&destination, 100).await.unwrap();
+    let disable_ix = env.ix(59, &[], vec![
+        writable(env.config),
+        writable(env.mint),
+        signer(admin),
+        readonly(spl_token::id()),
+    ]);
+
+    let events = env.send_for_events(std::slice::from_ref(&disable_ix), &[]).await;
+    assert_eq!(events, vec![AdminEvent::SupplyFrozen { signer: admin, final_supply: 100 }]);
+    assert!(env.config().await.mint_permanently_disabled);
+    let mint = env.mint;
+    let mint = env.account(&mint).await.unwrap();
+    assert_eq!(Mint::unpack(&mint.data).unwrap().mint_authority, COption::None);
+
+    assert_custom_error(env.mint_to(&destination, 1).await, CustomError::MintingFinalized);
+    assert_custom_error(env.send(&[disable_ix], &[]).await, CustomError::MintingFinalized);
+    assert_eq!(env.supply().await, 100);
+}
+
+#[tokio::test]
+async fn mint_names_a_config_mint_that_is_not_a_usable_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let mint = env.mint;
+    let rent = env.ctx.banks_client.get_rent().await.unwrap();
+    let swap_mint_data = |env: &mut TestEnv, data: Vec<u8>| {
+        let lamports = rent.minimum_balance(data.len());
+        let account = SdkAccount { lamports, data, owner: spl_token::id(), executable: false, rent_epoch: 0 };
+        env.ctx.set_account(&mint, &account.into());
+    };
+
+    // A token account sitting where the mint should be, and a mint-sized account never initialized
+    swap_mint_data(&mut env, vec![0; Account::LEN]);
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::InvalidMint);
+    swap_mint_data(&mut env, vec![0; Mint::LEN]);
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::InvalidMint);
+
+    // A real mint other than the config's is a mismatch, not an invalid mint
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts[0] = writable(other_mint);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::MintMismatch);
+}
@@ -0,0 +1,417 @@
+// Behavior tests: the processor runs natively inside solana-program-test, with the real SPL token programs
+// behind every CPI. Each file covers one area of the program; this module holds the shared setup
+
+mod admin;
+mod airdrop;
+mod audit;
+mod burn;
+mod dispatch;
+mod escrow;
+mod governance;
+mod initialize;
+mod migration;
+mod mint;
+mod pool;
+mod roles;
+mod token_ops;
+mod transfer;
+mod vesting;
+mod views;
+
+use std::sync::Once;
+
+use base64::Engine;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account as SdkAccount,
+    compute_budget::ComputeBudgetInstruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account, Mint};
+
+use crate::{config_address, error::CustomError, process_instruction, AdminEvent, TokenConfig, SUPPLY_HISTORY_SEED};
+
+pub const DECIMALS: u8 = 6;
+pub const MAX_SUPPLY: u64 = 1_000_000;
+
+pub type TestResult = Result<(), BanksClientError>;
+
+pub struct TestEnv {
+    pub ctx: ProgramTestContext,
+    pub program_id: Pubkey,
+    // Pays for everything, and is the mint authority, the freeze authority and the config admin
+    pub admin: Keypair,
+    pub mint: Pubkey,
+    pub config: Pubkey,
+    nonce: u32,
+}
+
+// Instruction data as the dispatcher expects it, a 1-byte tag or the 4-byte one under wide-discriminator
+pub fn instruction_data(tag: u8, payload: &[u8]) -> Vec<u8> {
+    #[cfg(not(feature = "wide-discriminator"))]
+    let mut data = vec![tag];
+    #[cfg(feature = "wide-discriminator")]
+    let mut data = (tag as u32).to_le_bytes().to_vec();
+    data.extend_from_slice(payload);
+    data
+}
+
+pub fn writable(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new(pubkey, false)
+}
+
+pub fn readonly(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new_readonly(pubkey, false)
+}
+
+pub fn signer(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new(pubkey, true)
+}
+
+pub fn instruction_error(result: TestResult) -> InstructionError {
+    match result.expect_err("instruction should have failed") {
+        BanksClientError::TransactionError(TransactionError::InstructionError(_, error))
+        | BanksClientError::SimulationError { err: TransactionError::InstructionError(_, error), .. } => error,
+        other => panic!("unexpected failure: {:?}", other),
+    }
+}
+
+pub fn assert_custom_error(result: TestResult, expected: CustomError) {
+    assert_eq!(instruction_error(result), InstructionError::Custom(expected as u32));
+}
+
+// program-test's syscall stubs drop sol_log_data, so natively the events would never reach the transaction
+// logs. These forward everything to them and turn each sol_log_data into the line the runtime would log
+struct EventLogStubs {
+    inner: Box<dyn SyscallStubs>,
+}
+
+// Only held while EventLogStubs takes program-test's stubs over
+struct PlaceholderStubs;
+
+impl SyscallStubs for PlaceholderStubs {}
+
+impl SyscallStubs for EventLogStubs {
+    fn sol_log(&self, message: &str) {
+        self.inner.sol_log(message)
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let fields: Vec<String> = fields.iter().map(|field| engine.encode(field)).collect();
+        self.inner.sol_log(&format!("Program data: {}", fields.join(" ")))
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.inner.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_clock_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_epoch_rewards_sysvar(var_addr)
+    }
+
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_fees_sysvar(var_addr)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_rent_sysvar(var_addr)
+    }
+
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.inner.sol_get_last_restart_slot(var_addr)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.inner.sol_get_return_data()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.inner.sol_set_return_data(data)
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        self.inner.sol_get_stack_height()
+    }
+}
+
+// Every TestEnv comes through here after program-test has installed its stubs and before it sends anything,
+// so no instruction runs against the placeholder
+fn install_event_log_stubs() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let inner = set_syscall_stubs(Box::new(PlaceholderStubs));
+        set_syscall_stubs(Box::new(EventLogStubs { inner }));
+    });
+}
+
+impl TestEnv {
+    // A mint with DECIMALS and an initialized config capped at max_supply, both under the admin
+    pub async fn new(max_supply: u64) -> Self {
+        let mut env = Self::with_mint().await;
+        env.initialize(max_supply).await.unwrap();
+        env
+    }
+
+    // The mint exists but its config hasn't been initialized yet
+    pub async fn with_mint() -> Self {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new("codex", program_id, processor!(process_instruction));
+        program_test.prefer_bpf(false);
+        let ctx = program_test.start_with_context().await;
+        install_event_log_stubs();
+        let admin = ctx.payer.insecure_clone();
+
+        let mut env = TestEnv {
+            ctx,
+            program_id,
+            admin,
+            mint: Pubkey::default(),
+            config: Pubkey::default(),
+            nonce: 0,
+        };
+        let admin = env.admin.pubkey();
+        env.mint = env.create_mint(&admin, DECIMALS).await;
+        env.config = config_address(&program_id, &env.mint).0;
+        env
+    }
+
+    pub async fn initialize(&mut self, max_supply: u64) -> TestResult {
+        let mut payload = self.admin.pubkey().to_bytes().to_vec();
+        payload.extend_from_slice(&max_supply.to_le_bytes());
+        let ix = self.ix(3, &payload, vec![
+            writable(self.config),
+            readonly(self.mint),
+            signer(self.admin.pubkey()),
+            readonly(system_program::id()),
+        ]);
+        self.send(&[ix], &[]).await
+    }
+
+    pub fn ix(&self, tag: u8, payload: &[u8], accounts: Vec<AccountMeta>) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: instruction_data(tag, payload),
+        }
+    }
+
+    // Signs with the admin plus `signers`. A compute budget instruction varying per call keeps otherwise
+    // identical transactions from being deduplicated against the same blockhash
+    pub async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> TestResult {
+        self.nonce += 1;
+        let mut all_instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_400_000 - self.nonce)];
+        all_instructions.extend_from_slice(instructions);
+
+        let mut all_signers = vec![&self.admin];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &all_instructions,
+            Some(&self.admin.pubkey()),
+            &all_signers,
+            self.ctx.last_blockhash,
+        );
+        self.ctx.banks_client.process_transaction(transaction).await
+    }
+
+    // Like send, returning the AdminEvents the transaction logged, in order. Natively they arrive through
+    // EventLogStubs as ordinary log lines
+    pub async fn send_for_events(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Vec<AdminEvent> {
+        self.nonce += 1;
+        let mut all_signers = vec![&self.admin];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.admin.pubkey()),
+            &all_signers,
+            self.ctx.last_blockhash,
+        );
+        let outcome = self.ctx.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+        outcome.result.unwrap();
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        outcome.metadata.expect("transaction metadata").log_messages.iter()
+            .filter_map(|line| line.strip_prefix("Program log: ").and_then(|line| line.strip_prefix("Program data: ")))
+            .filter_map(|data| {
+                let fields: Vec<Vec<u8>> = data.split(' ').map(|field| engine.decode(field).unwrap()).collect();
+                match fields.as_slice() {
+                    [tag, event] if tag == b"admin_event" => Some(AdminEvent::try_from_slice(event).unwrap()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    // Runs ix without committing it, for the read-only instructions; returns its return data and logs
+    pub async fn simulate(&mut self, ix: Instruction) -> (Vec<u8>, Vec<String>) {
+        let transaction =
+            Transaction::new_signed_with_payer(&[ix], Some(&self.admin.pubkey()), &[&self.admin], self.ctx.last_blockhash);
+        let simulation = self.ctx.banks_client.simulate_transaction(transaction).await.unwrap();
+        simulation.result.expect("simulated transaction").unwrap();
+        let details = simulation.simulation_details.expect("simulation details");
+        (details.return_data.map(|return_data| return_data.data).unwrap_or_default(), details.logs)
+    }
+
+    pub async fn create_mint(&mut self, authority: &Pubkey, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        self.create_mint_at(&mint, authority, decimals).await;
+        mint.pubkey()
+    }
+
+    // For mints whose authority is a PDA derived from the mint's own address
+    pub async fn create_mint_at(&mut self, mint: &Keypair, authority: &Pubkey, decimals: u8) {
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.admin.pubkey(),
+                &mint.pubkey(),
+                rent.minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), authority, Some(authority), decimals)
+                .unwrap(),
+        ];
+        self.send(&instructions, &[mint]).await.unwrap();
+    }
+
+    pub async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.admin.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(Account::LEN),
+                Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), mint, owner).unwrap(),
+        ];
+        self.send(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    // An empty token account of the config's mint
+    pub async fn token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let mint = self.mint;
+        self.create_token_account(&mint, owner).await
+    }
+
+    // A fresh wallet with enough SOL to pay for its own records
+    pub async fn funded_wallet(&mut self) -> Keypair {
+        let wallet = Keypair::new();
+        let ix = system_instruction::transfer(&self.admin.pubkey(), &wallet.pubkey(), 1_000_000_000);
+        self.send(&[ix], &[]).await.unwrap();
+        wallet
+    }
+
+    // A token account of the config's mint, owned by `owner` and holding `amount`
+    pub async fn funded_account(&mut self, owner: &Pubkey, amount: u64) -> Pubkey {
+        let account = self.token_account(owner).await;
+        self.mint_to(&account, amount).await.unwrap();
+        account
+    }
+
+    // Lamports sent to an address before anything creates it, as anyone may do to a PDA
+    pub fn prefund(&mut self, address: &Pubkey, lamports: u64) {
+        let account = SdkAccount { lamports, data: vec![], owner: system_program::id(), executable: false, rent_epoch: 0 };
+        self.ctx.set_account(address, &account.into());
+    }
+
+    pub async fn set_time(&mut self, unix_timestamp: i64) {
+        let clock = self.ctx.banks_client.get_sysvar::<Clock>().await.unwrap();
+        self.ctx.set_sysvar(&Clock { unix_timestamp, ..clock });
+    }
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<SdkAccount> {
+        self.ctx.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn balance(&mut self, token_account: &Pubkey) -> u64 {
+        let account = self.account(token_account).await.expect("token account exists");
+        Account::unpack(&account.data).unwrap().amount
+    }
+
+    pub async fn supply(&mut self) -> u64 {
+        let mint = self.mint;
+        let account = self.account(&mint).await.expect("mint exists");
+        Mint::unpack(&account.data).unwrap().supply
+    }
+
+    pub async fn config(&mut self) -> TokenConfig {
+        let config = self.config;
+        let account = self.account(&config).await.expect("config exists");
+        TokenConfig::deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    // Overwrites the stored config directly, for states no instruction sequence can reach in a test
+    pub async fn write_config(&mut self, token_config: &TokenConfig) {
+        let config = self.config;
+        let mut account = self.account(&config).await.expect("config exists");
+        token_config.serialize(&mut &mut account.data[..]).unwrap();
+        self.ctx.set_account(&config, &account.into());
+    }
+
+    pub async fn update_config(&mut self, field: u8, value: &[u8]) -> TestResult {
+        let mut payload = vec![field];
+        payload.extend_from_slice(value);
+        let ix = self.ix(4, &payload, vec![writable(self.config), signer(self.admin.pubkey())]);
+        self.send(&[ix], &[]).await
+    }
+
+    // The config's supply history, created on first use
+    pub async fn supply_history(&mut self) -> Pubkey {
+        let (history, _) = Pubkey::find_program_address(&[SUPPLY_HISTORY_SEED, self.config.as_ref()], &self.program_id);
+        if self.account(&history).await.is_none() {
+            let ix = self.ix(57, &[], vec![
+                readonly(self.config),
+                signer(self.admin.pubkey()),
+                writable(history),
+                readonly(system_program::id()),
+            ]);
+            self.send(&[ix], &[]).await.unwrap();
+        }
+        history
+    }
+
+    pub fn mint_ix(&self, destination: &Pubkey, amount: u64) -> Instruction {
+        self.ix(1, &amount.to_le_bytes(), vec![
+            writable(self.mint),
+            writable(*destination),
+            signer(self.admin.pubkey()),
+            readonly(spl_token::id()),
+            writable(self.config),
+        ])
+    }
+
+    pub async fn mint_to(&mut self, destination: &Pubkey, amount: u64) -> TestResult {
+        let ix = self.mint_ix(destination, amount);
+        self.send(&[ix], &[]).await
+    }
+}
@@ -0,0 +1,180 @@
+use std::convert::TryInto;
+
+use super::*;
+use crate::{get_amount_out, PoolConfig, MIN_POOL_LIQUIDITY, POOL_SEED};
+
+struct Pool {
+    address: Pubkey,
+    mint_b: Pubkey,
+    vault_a: Pubkey,
+    vault_b: Pubkey,
+    lp_mint: Pubkey,
+}
+
+// Two vaults funded with the minimum liquidity and an LP mint already handed to the pool PDA
+async fn prepare_pool(env: &mut TestEnv) -> Pool {
+    let admin = env.admin.pubkey();
+    let mint_b = env.create_mint(&admin, DECIMALS).await;
+    let address = Pubkey::find_program_address(&[POOL_SEED, env.mint.as_ref(), mint_b.as_ref()], &env.program_id).0;
+    let vault_a = env.funded_account(&admin, MIN_POOL_LIQUIDITY).await;
+    let vault_b = env.create_token_account(&mint_b, &admin).await;
+    let ix = spl_token::instruction::mint_to(&spl_token::id(), &mint_b, &vault_b, &admin, &[], 4 * MIN_POOL_LIQUIDITY)
+        .unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    let lp_mint = env.create_mint(&address, DECIMALS).await;
+    Pool { address, mint_b, vault_a, vault_b, lp_mint }
+}
+
+fn init_pool_ix(env: &TestEnv, pool: &Pool, fee_bps: u16) -> Instruction {
+    env.ix(10, &fee_bps.to_le_bytes(), vec![
+        writable(pool.address),
+        signer(env.admin.pubkey()),
+        readonly(env.mint),
+        readonly(pool.mint_b),
+        readonly(pool.vault_a),
+        readonly(pool.vault_b),
+        readonly(pool.lp_mint),
+        readonly(system_program::id()),
+    ])
+}
+
+fn reserves_ix(env: &TestEnv, pool: &Pool) -> Instruction {
+    env.ix(28, &[], vec![
+        readonly(pool.address),
+        readonly(pool.vault_a),
+        readonly(pool.vault_b),
+        readonly(pool.lp_mint),
+    ])
+}
+
+#[tokio::test]
+async fn pool_records_its_mints_vaults_and_fee() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+
+    env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await.unwrap();
+    let account = env.account(&pool.address).await.unwrap();
+    assert_eq!(account.owner, env.program_id);
+    let pool_config = PoolConfig::try_from_slice(&account.data).unwrap();
+    assert!(pool_config.initialized);
+    assert_eq!((pool_config.mint_a, pool_config.mint_b), (env.mint, pool.mint_b));
+    assert_eq!((pool_config.vault_a, pool_config.vault_b, pool_config.lp_mint), (pool.vault_a, pool.vault_b, pool.lp_mint));
+    assert_eq!(pool_config.fee_bps, 30);
+}
+
+#[tokio::test]
+async fn pool_cannot_be_initialized_twice() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+    env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await.unwrap();
+
+    let result = env.send(&[init_pool_ix(&env, &pool, 50)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::AccountAlreadyInitialized);
+    let pool_config = PoolConfig::try_from_slice(&env.account(&pool.address).await.unwrap().data).unwrap();
+    assert_eq!(pool_config.fee_bps, 30);
+}
+
+#[tokio::test]
+async fn prefunded_pool_pda_is_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+    env.prefund(&pool.address, 1);
+
+    env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await.unwrap();
+    let account = env.account(&pool.address).await.unwrap();
+    assert_eq!(account.owner, env.program_id);
+    assert!(PoolConfig::try_from_slice(&account.data).unwrap().initialized);
+}
+
+#[tokio::test]
+async fn reserves_are_read_from_the_pools_vaults() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+    env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await.unwrap();
+
+    let (data, _) = env.simulate(reserves_ix(&env, &pool)).await;
+    let reserves: Vec<u64> = data.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect();
+    assert_eq!(reserves, [MIN_POOL_LIQUIDITY, 4 * MIN_POOL_LIQUIDITY, 0]);
+
+    let mut ix = reserves_ix(&env, &pool);
+    ix.accounts.swap(1, 2);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn pool_needs_distinct_mints_at_its_own_address() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+
+    let mut ix = init_pool_ix(&env, &pool, 30);
+    ix.accounts[3] = readonly(env.mint);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidArgument);
+
+    let mut ix = init_pool_ix(&env, &pool, 30);
+    ix.accounts[0] = writable(Pubkey::new_unique());
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidSeeds);
+
+    let ix = init_pool_ix(&env, &pool, 10_001);
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn pool_vaults_must_hold_their_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let pool = prepare_pool(&mut env).await;
+
+    let mut swapped = init_pool_ix(&env, &pool, 30);
+    swapped.accounts.swap(4, 5);
+    assert_custom_error(env.send(&[swapped], &[]).await, CustomError::MintMismatch);
+    assert!(env.account(&pool.address).await.is_none());
+}
+
+#[tokio::test]
+async fn pool_vaults_need_the_minimum_liquidity() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let mut pool = prepare_pool(&mut env).await;
+    let admin = env.admin.pubkey();
+
+    pool.vault_a = env.funded_account(&admin, MIN_POOL_LIQUIDITY - 1).await;
+    let result = env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await;
+    assert_custom_error(result, CustomError::InsufficientLiquidity);
+    assert!(env.account(&pool.address).await.is_none());
+}
+
+#[tokio::test]
+async fn lp_mint_must_belong_to_the_pool() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let mut pool = prepare_pool(&mut env).await;
+    let admin = env.admin.pubkey();
+
+    // An LP mint the admin can still mint from would let it issue claims on the vaults
+    pool.lp_mint = env.create_mint(&admin, DECIMALS).await;
+    let result = env.send(&[init_pool_ix(&env, &pool, 30)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+    assert!(env.account(&pool.address).await.is_none());
+}
+
+#[tokio::test]
+async fn quote_uses_the_live_vault_balances() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let vault_a = env.funded_account(&admin, MIN_POOL_LIQUIDITY).await;
+    let vault_b = env.funded_account(&admin, 4 * MIN_POOL_LIQUIDITY).await;
+    let quote_ix = |env: &TestEnv, vault_in: Pubkey, vault_out: Pubkey| {
+        let mut payload = 500u64.to_le_bytes().to_vec();
+        payload.extend_from_slice(&30u16.to_le_bytes());
+        env.ix(23, &payload, vec![readonly(vault_in), readonly(vault_out)])
+    };
+
+    let (data, _) = env.simulate(quote_ix(&env, vault_a, vault_b)).await;
+    let expected = get_amount_out(500, MIN_POOL_LIQUIDITY, 4 * MIN_POOL_LIQUIDITY, 30).unwrap();
+    assert_eq!(data, expected.to_le_bytes());
+
+    env.mint_to(&vault_a, MIN_POOL_LIQUIDITY).await.unwrap();
+    let (data, _) = env.simulate(quote_ix(&env, vault_a, vault_b)).await;
+    let expected = get_amount_out(500, 2 * MIN_POOL_LIQUIDITY, 4 * MIN_POOL_LIQUIDITY, 30).unwrap();
+    assert_eq!(data, expected.to_le_bytes());
+
+    let result = env.send(&[quote_ix(&env, vault_a, vault_a)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidArgument);
+}
@@ -0,0 +1,185 @@
+use super::*;
+
+struct Roles {
+    mint_admin: Keypair,
+    config_admin: Keypair,
+}
+
+async fn split_roles(env: &mut TestEnv) -> Roles {
+    let roles = Roles { mint_admin: Keypair::new(), config_admin: Keypair::new() };
+    let mut payload = roles.mint_admin.pubkey().to_bytes().to_vec();
+    payload.extend_from_slice(roles.config_admin.pubkey().as_ref());
+    let ix = env.ix(36, &payload, vec![writable(env.config), signer(env.admin.pubkey())]);
+    env.send(&[ix], &[]).await.unwrap();
+    roles
+}
+
+fn update_config_ix(env: &TestEnv, signer_key: Pubkey) -> Instruction {
+    let mut payload = vec![0];
+    payload.extend_from_slice(&5u64.to_le_bytes());
+    env.ix(4, &payload, vec![writable(env.config), signer(signer_key)])
+}
+
+fn advance_epoch_ix(env: &TestEnv, signer_key: Pubkey) -> Instruction {
+    env.ix(27, &[], vec![writable(env.config), signer(signer_key)])
+}
+
+#[tokio::test]
+async fn the_admin_keeps_no_role_it_has_handed_out() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let roles = split_roles(&mut env).await;
+    let admin = env.admin.pubkey();
+
+    let ix = update_config_ix(&env, admin);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::Unauthorized);
+    let ix = advance_epoch_ix(&env, admin);
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::Unauthorized);
+
+    // The admin is still the token program's mint authority, but minting belongs to the mint admin now
+    let destination = env.token_account(&admin).await;
+    assert_custom_error(env.mint_to(&destination, 10).await, CustomError::UnauthorizedMint);
+
+    let ix = update_config_ix(&env, roles.config_admin.pubkey());
+    env.send(&[ix], &[&roles.config_admin]).await.unwrap();
+    let ix = advance_epoch_ix(&env, roles.config_admin.pubkey());
+    env.send(&[ix], &[&roles.config_admin]).await.unwrap();
+    let config = env.config().await;
+    assert_eq!(config.max_mint_operations, 5);
+    assert_eq!(config.current_epoch, 1);
+}
+
+#[tokio::test]
+async fn each_role_is_limited_to_its_own_handlers() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let roles = split_roles(&mut env).await;
+
+    let ix = update_config_ix(&env, roles.mint_admin.pubkey());
+    assert_custom_error(env.send(&[ix], &[&roles.mint_admin]).await, CustomError::Unauthorized);
+
+    let admin = env.admin.pubkey();
+    let destination = env.token_account(&admin).await;
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts[2] = signer(roles.config_admin.pubkey());
+    assert_custom_error(env.send(&[ix], &[&roles.config_admin]).await, CustomError::UnauthorizedMint);
+}
+
+#[tokio::test]
+async fn rotating_the_mint_authority_moves_only_the_mint_role() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let new_authority = Keypair::new();
+
+    let ix = env.ix(29, new_authority.pubkey().as_ref(), vec![
+        writable(env.config),
+        writable(env.mint),
+        signer(admin),
+        readonly(spl_token::id()),
+    ]);
+    env.send(&[ix], &[]).await.unwrap();
+    let config = env.config().await;
+    assert_eq!(config.mint_admin(), new_authority.pubkey());
+    assert_eq!(config.admin_pubkey, admin);
+    assert_eq!(config.config_admin(), admin);
+
+    let destination = env.token_account(&admin).await;
+    let mut ix = env.mint_ix(&destination, 10);
+    ix.accounts[2] = signer(new_authority.pubkey());
+    env.send(&[ix], &[&new_authority]).await.unwrap();
+    assert_eq!(env.supply().await, 10);
+}
+
+// Every privileged instruction, with `admin` in the slot its handler checks against the config, left unsigned
+fn unsigned_admin_instructions(env: &TestEnv, admin: Pubkey) -> Vec<(u8, Instruction)> {
+    let key = Pubkey::new_unique();
+    let amount = 10u64.to_le_bytes();
+    let mut update = vec![0];
+    update.extend_from_slice(&amount);
+    let mut vesting = 1i64.to_le_bytes().to_vec();
+    vesting.extend_from_slice(&2i64.to_le_bytes());
+    vesting.extend_from_slice(&3i64.to_le_bytes());
+    vesting.extend_from_slice(&amount);
+    let mut ratio = 1u64.to_le_bytes().to_vec();
+    ratio.extend_from_slice(&1u64.to_le_bytes());
+    let mut exemptions = vec![1];
+    exemptions.extend_from_slice(key.as_ref());
+    let config_and_admin = || vec![writable(env.config), readonly(admin)];
+    let wallet_record = || {
+        vec![readonly(env.config), readonly(admin), readonly(key), writable(key), readonly(system_program::id())]
+    };
+
+    let mut mint_ix = env.mint_ix(&key, 10);
+    mint_ix.accounts[2] = readonly(admin);
+    vec![
+        (1, mint_ix),
+        (5, env.ix(5, &amount, vec![
+            writable(env.mint),
+            readonly(admin),
+            readonly(spl_token::id()),
+            writable(env.config),
+            writable(key),
+        ])),
+        (4, env.ix(4, &update, config_and_admin())),
+        (15, env.ix(15, &vesting, vec![
+            readonly(env.config),
+            readonly(admin),
+            writable(key),
+            readonly(key),
+            readonly(key),
+            readonly(system_program::id()),
+        ])),
+        (20, env.ix(20, &[0], config_and_admin())),
+        (21, env.ix(21, &update, config_and_admin())),
+        (22, env.ix(22, &[], config_and_admin())),
+        (27, env.ix(27, &[], config_and_admin())),
+        (29, env.ix(29, key.as_ref(), vec![
+            writable(env.config),
+            writable(env.mint),
+            readonly(admin),
+            readonly(spl_token::id()),
+        ])),
+        (31, env.ix(31, &[1], wallet_record())),
+        (33, env.ix(33, &[1], wallet_record())),
+        (34, env.ix(34, &amount, vec![
+            writable(env.config),
+            readonly(admin),
+            writable(key),
+            writable(env.mint),
+            readonly(spl_token::id()),
+            writable(key),
+        ])),
+        (36, env.ix(36, &[key.to_bytes(), key.to_bytes()].concat(), config_and_admin())),
+        (42, env.ix(42, key.as_ref(), config_and_admin())),
+        (49, env.ix(49, &[], config_and_admin())),
+        (50, env.ix(50, &ratio, vec![
+            readonly(env.config),
+            readonly(admin),
+            writable(key),
+            readonly(env.mint),
+            readonly(key),
+            readonly(system_program::id()),
+        ])),
+        (52, env.ix(52, &exemptions, config_and_admin())),
+        (59, env.ix(59, &[], vec![
+            writable(env.config),
+            writable(env.mint),
+            readonly(admin),
+            readonly(spl_token::id()),
+        ])),
+    ]
+}
+
+#[tokio::test]
+async fn privileged_instructions_need_the_admin_to_sign() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    // An admin other than the fee payer, so the transaction can go out without its signature. The roles are
+    // left unset and fall back to it
+    let admin = Pubkey::new_unique();
+    let token_config = TokenConfig { admin_pubkey: admin, ..env.config().await };
+    env.write_config(&token_config).await;
+
+    for (tag, ix) in unsigned_admin_instructions(&env, admin) {
+        let result = env.send(&[ix], &[]).await;
+        assert_eq!(instruction_error(result), InstructionError::MissingRequiredSignature, "tag {}", tag);
+    }
+    assert_eq!(env.config().await.try_to_vec().unwrap(), token_config.try_to_vec().unwrap());
+}
@@ -0,0 +1,170 @@
+use solana_program::program_option::COption;
+
+use super::*;
+use crate::MAX_BATCH_REVOKE;
+
+async fn token_state(env: &mut TestEnv, address: &Pubkey) -> Account {
+    Account::unpack(&env.account(address).await.expect("token account exists").data).unwrap()
+}
+
+fn approve_ix(env: &TestEnv, source: Pubkey, delegate: Pubkey, owner: Pubkey) -> Instruction {
+    env.ix(9, &40u64.to_le_bytes(), vec![
+        writable(source),
+        readonly(delegate),
+        signer(owner),
+        readonly(spl_token::id()),
+    ])
+}
+
+#[tokio::test]
+async fn approve_and_revoke_manage_the_delegate() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 100).await;
+    let delegate = Pubkey::new_unique();
+
+    env.send(&[approve_ix(&env, source, delegate, owner.pubkey())], &[&owner]).await.unwrap();
+    let state = token_state(&mut env, &source).await;
+    assert_eq!((state.delegate, state.delegated_amount), (COption::Some(delegate), 40));
+
+    let ix = env.ix(7, &[], vec![writable(source), signer(owner.pubkey()), readonly(spl_token::id())]);
+    env.send(&[ix], &[&owner]).await.unwrap();
+    assert_eq!(token_state(&mut env, &source).await.delegate, COption::None);
+}
+
+#[tokio::test]
+async fn owner_cannot_be_its_own_delegate() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 100).await;
+
+    let result = env.send(&[approve_ix(&env, source, owner.pubkey(), owner.pubkey())], &[&owner]).await;
+    assert_custom_error(result, CustomError::InvalidAmount);
+    assert_eq!(token_state(&mut env, &source).await.delegate, COption::None);
+}
+
+#[tokio::test]
+async fn batch_revoke_clears_every_pair() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let first = env.funded_wallet().await;
+    let second = env.funded_wallet().await;
+    let mut pairs = vec![];
+    for owner in [&first, &second] {
+        let source = env.funded_account(&owner.pubkey(), 10).await;
+        let ix = spl_token::instruction::approve(&spl_token::id(), &source, &Pubkey::new_unique(), &owner.pubkey(), &[], 5)
+            .unwrap();
+        env.send(&[ix], &[owner]).await.unwrap();
+        pairs.push((source, owner.pubkey()));
+    }
+    let batch_revoke_ix = |env: &TestEnv, pairs: &[(Pubkey, Pubkey)]| {
+        let mut accounts = vec![readonly(spl_token::id())];
+        for (source, owner) in pairs {
+            accounts.extend([writable(*source), signer(*owner)]);
+        }
+        env.ix(13, &[], accounts)
+    };
+
+    env.send(&[batch_revoke_ix(&env, &pairs)], &[&first, &second]).await.unwrap();
+    for (source, _) in &pairs {
+        assert_eq!(token_state(&mut env, source).await.delegate, COption::None);
+    }
+
+    let mut ix = batch_revoke_ix(&env, &pairs);
+    ix.accounts.pop();
+    assert_eq!(instruction_error(env.send(&[ix], &[&first]).await), InstructionError::NotEnoughAccountKeys);
+
+    let too_many = vec![pairs[0]; MAX_BATCH_REVOKE + 1];
+    let result = env.send(&[batch_revoke_ix(&env, &too_many)], &[&first]).await;
+    assert_custom_error(result, CustomError::TooManyAccounts);
+}
+
+#[tokio::test]
+async fn freeze_and_thaw_need_the_freeze_authority() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let account = env.funded_account(&admin, 10).await;
+    let freeze_ix = |env: &TestEnv, tag: u8, authority: Pubkey| {
+        env.ix(tag, &[], vec![writable(account), readonly(env.mint), signer(authority), readonly(spl_token::id())])
+    };
+
+    let outsider = Keypair::new();
+    let result = env.send(&[freeze_ix(&env, 11, outsider.pubkey())], &[&outsider]).await;
+    assert_custom_error(result, CustomError::Unauthorized);
+
+    env.send(&[freeze_ix(&env, 11, admin)], &[]).await.unwrap();
+    assert!(token_state(&mut env, &account).await.is_frozen());
+    env.send(&[freeze_ix(&env, 12, admin)], &[]).await.unwrap();
+    assert!(!token_state(&mut env, &account).await.is_frozen());
+
+    let ix = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &env.mint,
+        None,
+        spl_token::instruction::AuthorityType::FreezeAccount,
+        &admin,
+        &[],
+    )
+    .unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    assert_custom_error(env.send(&[freeze_ix(&env, 11, admin)], &[]).await, CustomError::NoFreezeAuthority);
+}
+
+#[tokio::test]
+async fn wrapped_sol_round_trips() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let wsol = Keypair::new();
+    let wrap_ix = |env: &TestEnv, lamports: u64, native_mint: Pubkey| {
+        env.ix(18, &lamports.to_le_bytes(), vec![
+            signer(admin),
+            signer(wsol.pubkey()),
+            readonly(native_mint),
+            readonly(admin),
+            readonly(spl_token::id()),
+            readonly(system_program::id()),
+        ])
+    };
+
+    let result = env.send(&[wrap_ix(&env, 1_000, Pubkey::new_unique())], &[&wsol]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+    assert_custom_error(env.send(&[wrap_ix(&env, 0, spl_token::native_mint::id())], &[&wsol]).await, CustomError::InvalidAmount);
+
+    env.send(&[wrap_ix(&env, 1_000, spl_token::native_mint::id())], &[&wsol]).await.unwrap();
+    env.send(&[wrap_ix(&env, 500, spl_token::native_mint::id())], &[&wsol]).await.unwrap();
+    assert_eq!(token_state(&mut env, &wsol.pubkey()).await.amount, 1_500);
+    let wsol_lamports = env.account(&wsol.pubkey()).await.unwrap().lamports;
+
+    let destination = Pubkey::new_unique();
+    let unwrap_ix = |env: &TestEnv, account: Pubkey| {
+        env.ix(19, &[], vec![writable(account), writable(destination), signer(admin), readonly(spl_token::id())])
+    };
+    let token_account = env.token_account(&admin).await;
+    let result = env.send(&[unwrap_ix(&env, token_account)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+
+    env.send(&[unwrap_ix(&env, wsol.pubkey())], &[]).await.unwrap();
+    assert!(env.account(&wsol.pubkey()).await.is_none());
+    assert_eq!(env.account(&destination).await.unwrap().lamports, wsol_lamports);
+}
+
+#[tokio::test]
+async fn only_empty_accounts_are_closed_by_their_owner() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let account = env.funded_account(&owner.pubkey(), 10).await;
+    let close_ix =
+        |env: &TestEnv, owner: Pubkey| env.ix(32, &[], vec![writable(account), signer(owner), readonly(spl_token::id())]);
+
+    let outsider = Keypair::new();
+    assert_custom_error(env.send(&[close_ix(&env, outsider.pubkey())], &[&outsider]).await, CustomError::Unauthorized);
+    assert_custom_error(env.send(&[close_ix(&env, owner.pubkey())], &[&owner]).await, CustomError::AccountNotEmpty);
+
+    let burn_ix =
+        spl_token::instruction::burn(&spl_token::id(), &account, &env.mint, &owner.pubkey(), &[], 10).unwrap();
+    env.send(&[burn_ix], &[&owner]).await.unwrap();
+    let rent = env.account(&account).await.unwrap().lamports;
+    let wallet_lamports = env.account(&owner.pubkey()).await.unwrap().lamports;
+    env.send(&[close_ix(&env, owner.pubkey())], &[&owner]).await.unwrap();
+    assert!(env.account(&account).await.is_none());
+    assert_eq!(env.account(&owner.pubkey()).await.unwrap().lamports, wallet_lamports + rent);
+}
@@ -0,0 +1,406 @@
+use super::*;
+use crate::{MAX_FEE_EXEMPT, MEMO_PROGRAM_ID, OPT_IN_SEED, VAULT_AUTHORITY_SEED, WHITELIST_SEED};
+
+struct Transfer {
+    owner: Keypair,
+    source: Pubkey,
+    destination: Pubkey,
+}
+
+// A wallet holding 1_000 tokens and an empty account of the same mint for someone else
+async fn prepare_transfer(env: &mut TestEnv) -> Transfer {
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 1_000).await;
+    let destination = env.token_account(&Pubkey::new_unique()).await;
+    Transfer { owner, source, destination }
+}
+
+fn transfer_ix(env: &TestEnv, transfer: &Transfer, amount: u64, extra_accounts: Vec<AccountMeta>) -> Instruction {
+    let mut accounts = vec![
+        writable(transfer.source),
+        writable(transfer.destination),
+        signer(transfer.owner.pubkey()),
+        readonly(spl_token::id()),
+        readonly(env.config),
+    ];
+    accounts.extend(extra_accounts);
+    env.ix(0, &amount.to_le_bytes(), accounts)
+}
+
+async fn send_transfer(env: &mut TestEnv, transfer: &Transfer, amount: u64, extra_accounts: Vec<AccountMeta>) -> TestResult {
+    let ix = transfer_ix(env, transfer, amount, extra_accounts);
+    env.send(&[ix], &[&transfer.owner]).await
+}
+
+fn wallet_record(env: &TestEnv, seed: &[u8], wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[seed, env.config.as_ref(), wallet.as_ref()], &env.program_id).0
+}
+
+#[tokio::test]
+async fn transfer_moves_tokens_with_the_owners_signature() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+
+    send_transfer(&mut env, &transfer, 400, vec![]).await.unwrap();
+    assert_eq!(env.balance(&transfer.source).await, 600);
+    assert_eq!(env.balance(&transfer.destination).await, 400);
+
+    let mut ix = transfer_ix(&env, &transfer, 400, vec![]);
+    ix.accounts[2].is_signer = false;
+    assert_eq!(instruction_error(env.send(&[ix], &[]).await), InstructionError::MissingRequiredSignature);
+}
+
+// Without the feature a trailing account is ignored, with it the transfer is refused
+#[tokio::test]
+async fn strict_accounts_refuses_a_trailing_account() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    let extra = vec![readonly(Pubkey::new_unique())];
+
+    let result = send_transfer(&mut env, &transfer, 100, extra).await;
+    if cfg!(feature = "strict-accounts") {
+        assert_custom_error(result, CustomError::TooManyAccounts);
+        assert_eq!(env.balance(&transfer.destination).await, 0);
+    } else {
+        result.unwrap();
+        assert_eq!(env.balance(&transfer.destination).await, 100);
+    }
+}
+
+#[tokio::test]
+async fn transfer_refuses_accounts_of_another_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let mut transfer = prepare_transfer(&mut env).await;
+    let admin = env.admin.pubkey();
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    transfer.destination = env.create_token_account(&other_mint, &Pubkey::new_unique()).await;
+
+    assert_custom_error(send_transfer(&mut env, &transfer, 400, vec![]).await, CustomError::MintMismatch);
+}
+
+#[tokio::test]
+async fn transfer_refuses_a_pair_of_another_mint() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let admin = env.admin.pubkey();
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    let source = env.create_token_account(&other_mint, &owner.pubkey()).await;
+    let ix = spl_token::instruction::mint_to(&spl_token::id(), &other_mint, &source, &admin, &[], 1_000).unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    let destination = env.create_token_account(&other_mint, &Pubkey::new_unique()).await;
+    let transfer = Transfer { owner, source, destination };
+
+    // The two accounts agree with each other, just not with the config
+    assert_custom_error(send_transfer(&mut env, &transfer, 400, vec![]).await, CustomError::MintMismatch);
+    assert_eq!(env.balance(&transfer.source).await, 1_000);
+}
+
+#[tokio::test]
+async fn max_amount_moves_the_whole_balance() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+
+    send_transfer(&mut env, &transfer, u64::MAX, vec![]).await.unwrap();
+    assert_eq!(env.balance(&transfer.source).await, 0);
+    assert_eq!(env.balance(&transfer.destination).await, 1_000);
+    assert_custom_error(send_transfer(&mut env, &transfer, u64::MAX, vec![]).await, CustomError::InvalidAmount);
+}
+
+#[tokio::test]
+async fn transfer_all_empties_the_source() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+
+    let mut ix = transfer_ix(&env, &transfer, 0, vec![]);
+    ix.data = instruction_data(38, &[]);
+    env.send(&[ix], &[&transfer.owner]).await.unwrap();
+    assert_eq!(env.balance(&transfer.source).await, 0);
+    assert_eq!(env.balance(&transfer.destination).await, 1_000);
+}
+
+// A 1% transfer fee paid into a treasury account of the admin's
+async fn charge_transfer_fee(env: &mut TestEnv) -> Pubkey {
+    let admin = env.admin.pubkey();
+    let treasury = env.token_account(&admin).await;
+    env.update_config(9, &100u16.to_le_bytes()).await.unwrap();
+    env.update_config(10, treasury.as_ref()).await.unwrap();
+    treasury
+}
+
+#[tokio::test]
+async fn transfer_fee_goes_to_the_treasury() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    let treasury = charge_transfer_fee(&mut env).await;
+
+    // 1% of 500 goes to the treasury and the recipient nets the rest
+    send_transfer(&mut env, &transfer, 500, vec![writable(treasury)]).await.unwrap();
+    assert_eq!(env.balance(&transfer.source).await, 500);
+    assert_eq!(env.balance(&transfer.destination).await, 495);
+    assert_eq!(env.balance(&treasury).await, 5);
+
+    let result = send_transfer(&mut env, &transfer, 500, vec![writable(transfer.destination)]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn fees_compound_into_the_yield_vault_once_they_reach_the_threshold() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    let admin_treasury = charge_transfer_fee(&mut env).await;
+    let (authority, _) = Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, env.config.as_ref()], &env.program_id);
+    let treasury = env.token_account(&authority).await;
+    let yield_vault = env.token_account(&Pubkey::new_unique()).await;
+    env.update_config(27, &10u64.to_le_bytes()).await.unwrap();
+    env.update_config(28, yield_vault.as_ref()).await.unwrap();
+
+    // The program can only sweep a treasury its vault authority holds
+    let compounding = |treasury: Pubkey| vec![writable(treasury), readonly(authority), writable(yield_vault)];
+    let result = send_transfer(&mut env, &transfer, 100, compounding(admin_treasury)).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+    env.update_config(10, treasury.as_ref()).await.unwrap();
+
+    // 5 and then 4 stay in the treasury, the 1 that takes it to 10 sends all of it on
+    send_transfer(&mut env, &transfer, 500, compounding(treasury)).await.unwrap();
+    send_transfer(&mut env, &transfer, 400, compounding(treasury)).await.unwrap();
+    assert_eq!((env.balance(&treasury).await, env.balance(&yield_vault).await), (9, 0));
+    send_transfer(&mut env, &transfer, 100, compounding(treasury)).await.unwrap();
+    assert_eq!((env.balance(&treasury).await, env.balance(&yield_vault).await), (0, 10));
+    assert_eq!(env.balance(&transfer.destination).await, 990);
+}
+
+#[tokio::test]
+async fn fee_exempt_accounts_pay_no_transfer_fee() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    let treasury = charge_transfer_fee(&mut env).await;
+
+    let ix = env.ix(42, transfer.destination.as_ref(), vec![writable(env.config), signer(env.admin.pubkey())]);
+    env.send(&[ix], &[]).await.unwrap();
+    send_transfer(&mut env, &transfer, 500, vec![]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 500);
+    assert_eq!(env.balance(&treasury).await, 0);
+}
+
+#[tokio::test]
+async fn transfer_limit_caps_each_transfer() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    env.update_config(6, &100u64.to_le_bytes()).await.unwrap();
+
+    assert_custom_error(send_transfer(&mut env, &transfer, 101, vec![]).await, CustomError::TransferLimitExceeded);
+    send_transfer(&mut env, &transfer, 100, vec![]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+}
+
+#[tokio::test]
+async fn lockup_holds_transfers_until_it_ends() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    env.set_time(1_000).await;
+    env.update_config(8, &2_000i64.to_le_bytes()).await.unwrap();
+
+    assert_custom_error(send_transfer(&mut env, &transfer, 100, vec![]).await, CustomError::TransfersLocked);
+    env.set_time(2_000).await;
+    send_transfer(&mut env, &transfer, 100, vec![]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+}
+
+#[tokio::test]
+async fn transfers_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    env.update_config(23, &[1]).await.unwrap();
+
+    assert_custom_error(send_transfer(&mut env, &transfer, 100, vec![]).await, CustomError::MigrationInProgress);
+}
+
+#[tokio::test]
+async fn opt_in_mode_needs_the_recipients_consent() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let mut transfer = prepare_transfer(&mut env).await;
+    let recipient = env.funded_wallet().await;
+    transfer.destination = env.token_account(&recipient.pubkey()).await;
+    env.update_config(12, &[1]).await.unwrap();
+    let opt_in = wallet_record(&env, OPT_IN_SEED, &recipient.pubkey());
+
+    let result = send_transfer(&mut env, &transfer, 100, vec![readonly(opt_in)]).await;
+    assert_custom_error(result, CustomError::RecipientNotOptedIn);
+
+    let ix = env.ix(30, &[], vec![
+        readonly(env.config),
+        signer(recipient.pubkey()),
+        writable(opt_in),
+        readonly(system_program::id()),
+    ]);
+    env.send(&[ix], &[&recipient]).await.unwrap();
+    send_transfer(&mut env, &transfer, 100, vec![readonly(opt_in)]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+}
+
+#[tokio::test]
+async fn whitelist_mode_needs_both_sides_listed() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let mut transfer = prepare_transfer(&mut env).await;
+    let recipient = Pubkey::new_unique();
+    transfer.destination = env.token_account(&recipient).await;
+    env.update_config(13, &[1]).await.unwrap();
+    let sender_entry = wallet_record(&env, WHITELIST_SEED, &transfer.owner.pubkey());
+    let recipient_entry = wallet_record(&env, WHITELIST_SEED, &recipient);
+    let entries = || vec![readonly(sender_entry), readonly(recipient_entry)];
+
+    let whitelist_ix = |env: &TestEnv, wallet: Pubkey, entry: Pubkey| {
+        env.ix(33, &[1], vec![
+            readonly(env.config),
+            signer(env.admin.pubkey()),
+            readonly(wallet),
+            writable(entry),
+            readonly(system_program::id()),
+        ])
+    };
+    let ix = whitelist_ix(&env, transfer.owner.pubkey(), sender_entry);
+    env.send(&[ix], &[]).await.unwrap();
+    let result = send_transfer(&mut env, &transfer, 100, entries()).await;
+    assert_custom_error(result, CustomError::AddressNotWhitelisted);
+
+    let ix = whitelist_ix(&env, recipient, recipient_entry);
+    env.send(&[ix], &[]).await.unwrap();
+    send_transfer(&mut env, &transfer, 100, entries()).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+}
+
+#[tokio::test]
+async fn sol_fee_is_paid_to_the_treasury_by_the_payer() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    let sol_treasury = Pubkey::new_unique();
+    env.update_config(15, &5_000_000u64.to_le_bytes()).await.unwrap();
+    env.update_config(16, sol_treasury.as_ref()).await.unwrap();
+
+    let payer = transfer.owner.pubkey();
+    let sol_fee_accounts = vec![signer(payer), writable(sol_treasury), readonly(system_program::id())];
+    send_transfer(&mut env, &transfer, 100, sol_fee_accounts).await.unwrap();
+    assert_eq!(env.account(&sol_treasury).await.unwrap().lamports, 5_000_000);
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+
+    let wrong_treasury = vec![signer(payer), writable(Pubkey::new_unique()), readonly(system_program::id())];
+    let result = send_transfer(&mut env, &transfer, 100, wrong_treasury).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn memo_rides_along_with_the_transfer() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+
+    let memo_ix = |env: &TestEnv, memo_program: Pubkey, memo: &[u8]| {
+        let mut ix = transfer_ix(env, &transfer, 100, vec![]);
+        let mut payload = 100u64.to_le_bytes().to_vec();
+        payload.extend_from_slice(memo);
+        ix.data = instruction_data(26, &payload);
+        ix.accounts.insert(0, readonly(memo_program));
+        ix
+    };
+    let ix = memo_ix(&env, MEMO_PROGRAM_ID, b"invoice 42");
+    env.send(&[ix], &[&transfer.owner]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+
+    let ix = memo_ix(&env, Pubkey::new_unique(), b"invoice 42");
+    assert_eq!(instruction_error(env.send(&[ix], &[&transfer.owner]).await), InstructionError::IncorrectProgramId);
+    let ix = memo_ix(&env, MEMO_PROGRAM_ID, &[0xff, 0xfe]);
+    assert_eq!(instruction_error(env.send(&[ix], &[&transfer.owner]).await), InstructionError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn checked_only_mode_refuses_plain_transfers() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let transfer = prepare_transfer(&mut env).await;
+    env.update_config(24, &[1]).await.unwrap();
+
+    assert_custom_error(send_transfer(&mut env, &transfer, 100, vec![]).await, CustomError::InvalidAmount);
+
+    let checked_ix = |env: &TestEnv, decimals: u8| {
+        let mut ix = transfer_ix(env, &transfer, 100, vec![]);
+        let mut payload = 100u64.to_le_bytes().to_vec();
+        payload.push(decimals);
+        ix.data = instruction_data(53, &payload);
+        ix.accounts.insert(0, readonly(env.mint));
+        ix
+    };
+    let ix = checked_ix(&env, DECIMALS + 1);
+    assert_custom_error(env.send(&[ix], &[&transfer.owner]).await, CustomError::DecimalsMismatch);
+    let ix = checked_ix(&env, DECIMALS);
+    env.send(&[ix], &[&transfer.owner]).await.unwrap();
+    assert_eq!(env.balance(&transfer.destination).await, 100);
+}
+
+#[tokio::test]
+async fn validate_batch_counts_the_transfers_that_fit() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let owner = env.funded_wallet().await;
+    let source = env.funded_account(&owner.pubkey(), 1_000).await;
+    let validate_ix = |env: &TestEnv, amounts: &[u64]| {
+        let payload: Vec<u8> = amounts.iter().flat_map(|amount| amount.to_le_bytes()).collect();
+        env.ix(6, &payload, vec![readonly(source)])
+    };
+
+    // Counting stops at the first transfer that doesn't fit, even if a later one would
+    let (data, _) = env.simulate(validate_ix(&env, &[300, 500, 400, 100])).await;
+    assert_eq!(data, 2u64.to_le_bytes());
+
+    let result = env.send(&[validate_ix(&env, &[300, 0])], &[]).await;
+    assert_custom_error(result, CustomError::InvalidAmount);
+    let mut ix = validate_ix(&env, &[300]);
+    ix.data.pop();
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::InvalidAmount);
+}
+
+#[tokio::test]
+async fn exemptions_batch_adds_and_removes_keys() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let keys: Vec<Pubkey> = (0..=MAX_FEE_EXEMPT).map(|_| Pubkey::new_unique()).collect();
+    let exemptions_ix = |env: &TestEnv, exempt: u8, keys: &[Pubkey]| {
+        let mut payload = vec![exempt];
+        for key in keys {
+            payload.extend_from_slice(key.as_ref());
+        }
+        env.ix(52, &payload, vec![writable(env.config), signer(env.admin.pubkey())])
+    };
+
+    env.send(&[exemptions_ix(&env, 1, &keys[..2])], &[]).await.unwrap();
+    env.send(&[exemptions_ix(&env, 1, &keys[1..3])], &[]).await.unwrap();
+    env.send(&[exemptions_ix(&env, 0, &keys[..1])], &[]).await.unwrap();
+    let exempt = env.config().await.fee_exempt;
+    assert!(!exempt.contains(&keys[0]) && exempt.contains(&keys[1]) && exempt.contains(&keys[2]));
+
+    // Filling the last free slots works, one more key past that doesn't
+    env.send(&[exemptions_ix(&env, 1, &keys[2..])], &[]).await.unwrap();
+    let exempt = env.config().await.fee_exempt;
+    let result = env.send(&[exemptions_ix(&env, 1, &keys[..1])], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidArgument);
+    let result = env.send(&[exemptions_ix(&env, 2, &keys[..1])], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+    let result = env.send(&[exemptions_ix(&env, 1, &[Pubkey::default()])], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidInstructionData);
+    assert_eq!(env.config().await.fee_exempt, exempt);
+}
+
+#[tokio::test]
+async fn quiet_logs_leave_only_the_cpi_logs_of_a_transfer() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    let source = env.funded_account(&admin, 100).await;
+    let destination = env.token_account(&Pubkey::new_unique()).await;
+    let ix = env.ix(0, &100u64.to_le_bytes(), vec![
+        writable(source),
+        writable(destination),
+        signer(admin),
+        readonly(spl_token::id()),
+        readonly(env.config),
+    ]);
+
+    let (_, logs) = env.simulate(ix).await;
+    let logged = logs.iter().any(|log| log.starts_with("Program log: Transferring 100 tokens"));
+    assert_eq!(logged, cfg!(not(feature = "quiet-logs")));
+    assert!(logs.iter().any(|log| log == "Program log: Instruction: Transfer"));
+}
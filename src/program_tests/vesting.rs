@@ -0,0 +1,155 @@
+use super::*;
+use crate::{VAULT_AUTHORITY_SEED, VESTING_SEED};
+
+const START: i64 = 1_000;
+const CLIFF: i64 = 2_000;
+const END: i64 = 11_000;
+const TOTAL: u64 = 1_000;
+
+struct Vesting {
+    beneficiary: Keypair,
+    schedule: Pubkey,
+    vault: Pubkey,
+    destination: Pubkey,
+}
+
+// A beneficiary with an empty destination, and a vault held by their schedule's own authority
+async fn fund_vesting(env: &mut TestEnv, funded: u64) -> Vesting {
+    let beneficiary = Keypair::new();
+    let (schedule, _) = Pubkey::find_program_address(
+        &[VESTING_SEED, env.config.as_ref(), beneficiary.pubkey().as_ref()],
+        &env.program_id,
+    );
+    let (authority, _) = Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, schedule.as_ref()], &env.program_id);
+    let vault = env.funded_account(&authority, funded).await;
+    let destination = env.token_account(&beneficiary.pubkey()).await;
+    Vesting { beneficiary, schedule, vault, destination }
+}
+
+fn create_vesting_ix(env: &TestEnv, vesting: &Vesting) -> Instruction {
+    let mut payload = START.to_le_bytes().to_vec();
+    payload.extend_from_slice(&CLIFF.to_le_bytes());
+    payload.extend_from_slice(&END.to_le_bytes());
+    payload.extend_from_slice(&TOTAL.to_le_bytes());
+    env.ix(15, &payload, vec![
+        readonly(env.config),
+        signer(env.admin.pubkey()),
+        writable(vesting.schedule),
+        readonly(vesting.beneficiary.pubkey()),
+        readonly(vesting.vault),
+        readonly(system_program::id()),
+    ])
+}
+
+async fn create_vesting(env: &mut TestEnv, vesting: &Vesting) -> TestResult {
+    let ix = create_vesting_ix(env, vesting);
+    env.send(&[ix], &[]).await
+}
+
+fn release_ix(env: &TestEnv, vesting: &Vesting, caller: Pubkey, token_program: Pubkey) -> Instruction {
+    let (authority, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, vesting.schedule.as_ref()], &env.program_id);
+    env.ix(16, &[], vec![
+        readonly(env.config),
+        writable(vesting.schedule),
+        signer(caller),
+        writable(vesting.vault),
+        readonly(authority),
+        writable(vesting.destination),
+        readonly(token_program),
+    ])
+}
+
+async fn release(env: &mut TestEnv, vesting: &Vesting) -> TestResult {
+    let ix = release_ix(env, vesting, vesting.beneficiary.pubkey(), spl_token::id());
+    env.send(&[ix], &[&vesting.beneficiary]).await
+}
+
+#[tokio::test]
+async fn release_follows_the_schedule() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL).await;
+    create_vesting(&mut env, &vesting).await.unwrap();
+
+    // Past the start but before the cliff nothing is releasable yet
+    env.set_time(CLIFF - 1).await;
+    release(&mut env, &vesting).await.unwrap();
+    assert_eq!(env.balance(&vesting.destination).await, 0);
+
+    env.set_time((START + END) / 2).await;
+    release(&mut env, &vesting).await.unwrap();
+    assert_eq!(env.balance(&vesting.destination).await, TOTAL / 2);
+
+    env.set_time(END + 1).await;
+    release(&mut env, &vesting).await.unwrap();
+    assert_eq!(env.balance(&vesting.destination).await, TOTAL);
+    assert_eq!(env.balance(&vesting.vault).await, 0);
+}
+
+#[tokio::test]
+async fn release_needs_the_beneficiary_and_the_token_program() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL).await;
+    create_vesting(&mut env, &vesting).await.unwrap();
+    env.set_time(END).await;
+
+    let ix = release_ix(&env, &vesting, env.admin.pubkey(), spl_token::id());
+    assert_custom_error(env.send(&[ix], &[]).await, CustomError::Unauthorized);
+
+    let ix = release_ix(&env, &vesting, vesting.beneficiary.pubkey(), Pubkey::new_unique());
+    let result = env.send(&[ix], &[&vesting.beneficiary]).await;
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+    assert_eq!(env.balance(&vesting.vault).await, TOTAL);
+}
+
+#[tokio::test]
+async fn vesting_needs_a_fully_funded_vault_of_its_own() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL - 1).await;
+    assert_eq!(instruction_error(create_vesting(&mut env, &vesting).await), InstructionError::InsufficientFunds);
+
+    // A vault under the config-wide authority could be drained through another schedule or the airdrop
+    let (shared_authority, _) =
+        Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED, env.config.as_ref()], &env.program_id);
+    let shared_vault = env.funded_account(&shared_authority, TOTAL).await;
+    let vesting = Vesting { vault: shared_vault, ..vesting };
+    assert_eq!(instruction_error(create_vesting(&mut env, &vesting).await), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn creating_a_schedule_is_logged() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL).await;
+
+    let ix = create_vesting_ix(&env, &vesting);
+    let events = env.send_for_events(&[ix], &[]).await;
+    assert_eq!(events, vec![AdminEvent::VestingCreated {
+        signer: env.admin.pubkey(),
+        beneficiary: vesting.beneficiary.pubkey(),
+        vault: vesting.vault,
+        start_ts: START,
+        cliff_ts: CLIFF,
+        end_ts: END,
+        total: TOTAL,
+    }]);
+}
+
+#[tokio::test]
+async fn prefunded_schedule_pda_is_still_created() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL).await;
+    env.prefund(&vesting.schedule, 1);
+
+    create_vesting(&mut env, &vesting).await.unwrap();
+    assert_eq!(env.account(&vesting.schedule).await.unwrap().owner, env.program_id);
+}
+
+#[tokio::test]
+async fn schedules_wait_for_a_config_migration() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let vesting = fund_vesting(&mut env, TOTAL).await;
+    env.update_config(23, &[1]).await.unwrap();
+
+    assert_custom_error(create_vesting(&mut env, &vesting).await, CustomError::MigrationInProgress);
+    assert!(env.account(&vesting.schedule).await.is_none());
+}
@@ -0,0 +1,104 @@
+use std::convert::TryInto;
+
+use super::*;
+use crate::CONFIG_VERSION;
+
+fn u64_at(data: &[u8], index: usize) -> u64 {
+    u64::from_le_bytes(data[index * 8..(index + 1) * 8].try_into().unwrap())
+}
+
+#[tokio::test]
+async fn get_config_returns_the_stored_config() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    env.update_config(6, &250u64.to_le_bytes()).await.unwrap();
+
+    let ix = env.ix(8, &[], vec![readonly(env.config)]);
+    let (data, _) = env.simulate(ix).await;
+    assert_eq!(data, env.config().await.try_to_vec().unwrap());
+    let token_config = TokenConfig::try_from_slice(&data).unwrap();
+    assert_eq!(token_config.version, CONFIG_VERSION);
+    assert_eq!(token_config.max_transfer_amount, 250);
+}
+
+#[tokio::test]
+async fn headroom_is_what_is_left_under_the_cap() {
+    let mut env = TestEnv::new(1_000).await;
+    let admin = env.admin.pubkey();
+    env.funded_account(&admin, 300).await;
+    let headroom_ix = |env: &TestEnv, mint: Pubkey| env.ix(35, &[], vec![readonly(env.config), readonly(mint)]);
+
+    let (data, _) = env.simulate(headroom_ix(&env, env.mint)).await;
+    assert_eq!((u64_at(&data, 0), u64_at(&data, 1), u64_at(&data, 2)), (300, 1_000, 700));
+
+    // Supply past a lowered cap leaves no headroom rather than wrapping
+    let token_config = TokenConfig { max_supply: 200, ..env.config().await };
+    env.write_config(&token_config).await;
+    let (data, _) = env.simulate(headroom_ix(&env, env.mint)).await;
+    assert_eq!((u64_at(&data, 0), u64_at(&data, 1), u64_at(&data, 2)), (300, 200, 0));
+
+    let other_mint = env.create_mint(&admin, DECIMALS).await;
+    let result = env.send(&[headroom_ix(&env, other_mint)], &[]).await;
+    assert_custom_error(result, CustomError::MintMismatch);
+}
+
+#[tokio::test]
+async fn attestation_names_the_supply_slot_and_program() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let admin = env.admin.pubkey();
+    env.funded_account(&admin, 42).await;
+
+    let ix = env.ix(17, &[], vec![readonly(env.mint)]);
+    let (data, logs) = env.simulate(ix).await;
+    assert_eq!(data.len(), 48);
+    assert_eq!(u64_at(&data, 0), 42);
+    assert_eq!(&data[16..], env.program_id.as_ref());
+    let slot = u64_at(&data, 1);
+    assert!(logs.iter().any(|line| line.contains(&format!("at slot {} is 42", slot))));
+}
+
+#[tokio::test]
+async fn verify_config_needs_an_initialized_config_of_ours() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let verify_ix = |env: &TestEnv, config: Pubkey| env.ix(47, &[], vec![readonly(config)]);
+
+    let (data, _) = env.simulate(verify_ix(&env, env.config)).await;
+    assert_eq!(&data[..32], env.admin.pubkey().as_ref());
+    assert_eq!(data[32..], [1]);
+
+    let admin = env.admin.pubkey();
+    let result = env.send(&[verify_ix(&env, admin)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+
+    let token_config = TokenConfig { initialized: false, ..env.config().await };
+    env.write_config(&token_config).await;
+    let result = env.send(&[verify_ix(&env, env.config)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::UninitializedAccount);
+}
+
+#[tokio::test]
+async fn health_check_flags_the_deployment_state() {
+    let mut env = TestEnv::new(MAX_SUPPLY).await;
+    let health_ix = |env: &TestEnv, config: Pubkey| env.ix(54, &[], vec![readonly(config), readonly(env.mint)]);
+
+    let (data, _) = env.simulate(health_ix(&env, env.config)).await;
+    assert_eq!(data, [1, 1, 0, 0, 1]);
+
+    env.update_config(23, &[1]).await.unwrap();
+    let admin = env.admin.pubkey();
+    let ix = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &env.mint,
+        None,
+        spl_token::instruction::AuthorityType::MintTokens,
+        &admin,
+        &[],
+    )
+    .unwrap();
+    env.send(&[ix], &[]).await.unwrap();
+    let (data, _) = env.simulate(health_ix(&env, env.config)).await;
+    assert_eq!(data, [1, 1, 1, 1, 0]);
+
+    // Someone else's account reports only that it isn't ours
+    let (data, _) = env.simulate(health_ix(&env, admin)).await;
+    assert!(data.iter().all(|flag| *flag == 0));
+}